@@ -0,0 +1,148 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ohelpers_proc_macros::define::{Define, Defines};
+use ohelpers_proc_macros::punct_set::PunctSet;
+use proc_macro2::TokenStream;
+use syn::parse::Parser;
+use syn::{token, Ident, Type};
+
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+fn idents(n: usize) -> TokenStream {
+    let src = (0..n).map(|i| format!("ident_{}", i)).collect::<Vec<_>>().join(", ");
+    src.parse().unwrap()
+}
+
+// Nested generics, but without a comma directly inside the angle brackets:
+// the baseline splits `HashMap<K, V>` at its comma, so it can't parse those.
+fn types(n: usize) -> TokenStream {
+    let src = (0..n)
+        .map(|i| format!("Box<Option<Vec<(u8, Value{})>>>", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    src.parse().unwrap()
+}
+
+fn defines(n: usize) -> TokenStream {
+    let src = (0..n)
+        .map(|i| format!("key_{}: {{ width: {}, color: \"red\", kind: Vec<u8> }}", i, i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    src.parse().unwrap()
+}
+
+/// The implementation `PunctSet::parse` and `parse_until` had before they
+/// were made single-pass, as the baseline to compare against. Copied as it
+/// was, except for consuming the delimiter after every element (it only
+/// consumed the first one, failing on sets of more than two elements), and for
+/// the debug output.
+mod baseline {
+    use std::any::{Any, TypeId};
+    use std::fmt::Display;
+
+    use proc_macro2::{TokenStream, TokenTree};
+    use quote::ToTokens;
+    use syn::parse::{Parse, ParseStream};
+    use syn::token::Token;
+
+    pub fn parse_until<E: Token + Parse + Default + 'static>(
+        input: ParseStream,
+        _end: E,
+    ) -> syn::Result<TokenStream> {
+        let mut tokens = TokenStream::new();
+        while !input.is_empty() {
+            let fork = input.fork();
+            let tt: Option<TokenTree> = fork.parse().ok();
+            if let Some(t) = tt {
+                let ts = t.to_token_stream();
+                let e: Option<E> = syn::parse2::<E>(ts).ok();
+                if t.type_id() == TypeId::of::<E>() || e.is_some() {
+                    break;
+                }
+            }
+            let next: TokenTree = input.parse()?;
+            tokens.extend(Some(next));
+        }
+        Ok(tokens)
+    }
+
+    pub fn parse_punct_set<T, D>(input: ParseStream) -> syn::Result<Vec<T>>
+    where
+        T: Parse + Display + ToTokens + 'static,
+        D: Token + Parse + Default + 'static,
+    {
+        let mut vec: Vec<T> = Vec::new();
+        while !input.is_empty() {
+            let (token, next) = input.cursor().token_tree().unwrap();
+            let punct: Option<D> = syn::parse2(token.to_token_stream()).ok();
+            if next.eof() && punct.is_some() {
+                break;
+            }
+            let stream: TokenStream = parse_until(input, D::default())?;
+            let val: Option<T> = syn::parse2::<T>(stream.clone()).ok();
+            if val.is_none() {
+                panic!("Expected a {}, but found `{}`", std::any::type_name::<T>(), stream);
+            }
+            let _: Option<D> = input.parse().ok();
+            vec.push(val.unwrap());
+        }
+        Ok(vec)
+    }
+}
+
+fn bench_idents(c: &mut Criterion) {
+    let mut group = c.benchmark_group("idents");
+    for n in SIZES {
+        let input = idents(n);
+        group.bench_with_input(BenchmarkId::new("punct_set", n), &input, |b, input| {
+            b.iter(|| syn::parse2::<PunctSet<Ident>>(black_box(input.clone())).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("baseline", n), &input, |b, input| {
+            b.iter(|| {
+                let parser = baseline::parse_punct_set::<Ident, token::Comma>;
+                parser.parse2(black_box(input.clone())).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_types(c: &mut Criterion) {
+    let mut group = c.benchmark_group("types");
+    for n in SIZES {
+        let input = types(n);
+        group.bench_with_input(BenchmarkId::new("punct_set", n), &input, |b, input| {
+            b.iter(|| {
+                let split = syn::parse2::<PunctSet>(black_box(input.clone())).unwrap();
+                split.into_iter().map(syn::parse2::<Type>).collect::<syn::Result<Vec<_>>>().unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("baseline", n), &input, |b, input| {
+            b.iter(|| {
+                let parser = baseline::parse_punct_set::<TokenStream, token::Comma>;
+                let split = parser.parse2(black_box(input.clone())).unwrap();
+                split.into_iter().map(syn::parse2::<Type>).collect::<syn::Result<Vec<_>>>().unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_defines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("defines");
+    for n in SIZES {
+        let input = defines(n);
+        group.bench_with_input(BenchmarkId::new("punct_set", n), &input, |b, input| {
+            b.iter(|| syn::parse2::<Defines>(black_box(input.clone())).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("baseline", n), &input, |b, input| {
+            b.iter(|| {
+                let parser = baseline::parse_punct_set::<Define, token::Comma>;
+                parser.parse2(black_box(input.clone())).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_idents, bench_types, bench_defines);
+criterion_main!(benches);
@@ -0,0 +1,389 @@
+use proc_macro2::{Ident, Literal, Span};
+use quote::{format_ident, quote, ToTokens};
+use syn::{parse_quote, Expr, ExprLit, ExprUnary, Lit, Type, UnOp};
+
+use crate::define::{to_snake_case, Define, DefineSeparators, DefineValue, Defines};
+use crate::parse_utils::push_error;
+use crate::TokenStream2;
+
+/// How the `fn get(key: &str)` generated by `Defines::as_get_fn` finds the
+/// value of a key.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LookupStrategy {
+    /// A `match` on the key, e.g `"width" => Some(4),`.
+    #[default]
+    Match,
+    /// A binary search in a static table sorted by key, which requires the
+    /// values to be `Copy`.
+    BinarySearch,
+}
+
+impl<S: DefineSeparators> DefineValue<S> {
+    /// The type of a literal value (or a negated one, or a list of them), e.g
+    /// `i32` for `4`, `u8` for `4u8`, `&'static str` for `"foo"` and `[f64; 2]`
+    /// for `[1.0, 2.5]`.
+    pub fn infer_type(&self) -> syn::Result<Type> {
+        match self {
+            DefineValue::Lit(lit) => lit_type(lit).ok_or_else(|| self.infer_error()),
+            DefineValue::Expr(Expr::Unary(ExprUnary {
+                op: UnOp::Neg(_),
+                expr,
+                ..
+            })) => match expr.as_ref() {
+                Expr::Lit(ExprLit {
+                    lit: lit @ (Lit::Int(_) | Lit::Float(_)),
+                    ..
+                }) => lit_type(lit).ok_or_else(|| self.infer_error()),
+                _ => Err(self.infer_error()),
+            },
+            DefineValue::List(_, vals) => {
+                let first = match vals.first() {
+                    Some(first) => first.infer_type()?,
+                    None => return Err(syn::Error::new_spanned(self, "Can't infer the type of an empty list")),
+                };
+                for val in vals.iter().skip(1) {
+                    let ty = val.infer_type()?;
+                    if ty.to_token_stream().to_string() != first.to_token_stream().to_string() {
+                        return Err(syn::Error::new_spanned(
+                            val,
+                            format!(
+                                "Expected every item of the list to be `{}`, but found `{}`",
+                                first.to_token_stream(),
+                                ty.to_token_stream()
+                            ),
+                        ));
+                    }
+                }
+                let len = Literal::usize_unsuffixed(vals.len());
+                Ok(parse_quote!([#first; #len]))
+            },
+            _ => Err(self.infer_error()),
+        }
+    }
+
+    fn infer_error(&self) -> syn::Error {
+        syn::Error::new_spanned(
+            self,
+            format!(
+                "Can't infer the type of {}, only the types of literals (and lists of them) are \
+                 inferred",
+                self.describe()
+            ),
+        )
+    }
+}
+
+fn lit_type(lit: &Lit) -> Option<Type> {
+    let ty: Type = match lit {
+        Lit::Str(_) => parse_quote!(&'static str),
+        Lit::ByteStr(_) => parse_quote!(&'static [u8]),
+        Lit::Byte(_) => parse_quote!(u8),
+        Lit::Char(_) => parse_quote!(char),
+        Lit::Bool(_) => parse_quote!(bool),
+        Lit::Int(lit) => match lit.suffix() {
+            "" => parse_quote!(i32),
+            suffix => syn::parse_str(suffix).ok()?,
+        },
+        Lit::Float(lit) => match lit.suffix() {
+            "" => parse_quote!(f64),
+            suffix => syn::parse_str(suffix).ok()?,
+        },
+        _ => return None,
+    };
+    Some(ty)
+}
+
+impl<S: DefineSeparators> Defines<S> {
+    /// A `pub const` for every define, named after the key in
+    /// SCREAMING_SNAKE_CASE, with the type inferred from the value (see
+    /// `DefineValue::infer_type`), e.g `width: 4, label: "Foo"` becomes:
+    /// ```text
+    /// pub const WIDTH: i32 = 4;
+    /// pub const LABEL: &'static str = "Foo";
+    /// ```
+    pub fn as_consts(&self) -> syn::Result<TokenStream2> {
+        self.as_consts_with(|_| None)
+    }
+
+    /// Same as `as_consts`, but `ty_of` may give the type of a define, which is
+    /// then used instead of inferring it, e.g for values that aren't literals.
+    ///
+    /// Keys that become the same constant (e.g `width` and `Width`, or
+    /// `backgroundColor` and `background_color`) are an error.
+    pub fn as_consts_with(&self, ty_of: impl Fn(&Define<S>) -> Option<Type>) -> syn::Result<TokenStream2> {
+        let mut result = TokenStream2::new();
+        let mut errors: Option<syn::Error> = None;
+        let mut names: Vec<(Ident, &Define<S>)> = Vec::with_capacity(self.vals.len());
+        for val in &self.vals {
+            let name = const_name(val);
+            if let Some((_, first)) = names.iter().find(|(n, _)| *n == name) {
+                let mut e = syn::Error::new(
+                    val.ident.span(),
+                    format!(
+                        "Key `{}` becomes the constant `{}`, like `{}`",
+                        val.ident, name, first.ident
+                    ),
+                );
+                e.combine(syn::Error::new(
+                    first.ident.span(),
+                    format!("`{}` is first defined here", first.ident),
+                ));
+                push_error(&mut errors, e);
+                continue;
+            }
+            names.push((name.clone(), val));
+            let ty = match ty_of(val).map_or_else(|| val.value.infer_type(), Ok) {
+                Ok(ty) => ty,
+                Err(e) => {
+                    push_error(&mut errors, e);
+                    continue;
+                },
+            };
+            let value = &val.value;
+            result.extend(quote!(pub const #name: #ty = #value;));
+        }
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
+    }
+
+    /// A `pub static` table of the keys (as written) and values, e.g
+    /// `width: 4, height: 8` with the name `SIZES` becomes:
+    /// ```text
+    /// pub static SIZES: [(&str, i32); 2] = [("width", 4), ("height", 8)];
+    /// ```
+    /// Every value must be of the same type.
+    pub fn as_static_table(&self, name: &Ident) -> syn::Result<TokenStream2> {
+        self.as_static_table_with(name, |_| None)
+    }
+
+    /// Same as `as_static_table`, see `as_consts_with`.
+    pub fn as_static_table_with(
+        &self,
+        name: &Ident,
+        ty_of: impl Fn(&Define<S>) -> Option<Type>,
+    ) -> syn::Result<TokenStream2> {
+        let (ty, entries) = self.table_entries(&ty_of)?;
+        let len = Literal::usize_unsuffixed(entries.len());
+        let entries = entries.iter().map(|(key, value)| quote!((#key, #value)));
+        Ok(quote! {
+            pub static #name: [(&str, #ty); #len] = [#(#entries),*];
+        })
+    }
+
+    /// A `pub fn #name(key: &str) -> Option<Ty>` returning the value of the
+    /// key (as written), e.g with `LookupStrategy::Match`:
+    /// ```text
+    /// pub fn get(key: &str) -> Option<i32> {
+    ///     match key {
+    ///         "width" => Some(4),
+    ///         "height" => Some(8),
+    ///         _ => None,
+    ///     }
+    /// }
+    /// ```
+    /// Every value must be of the same type.
+    pub fn as_get_fn(&self, name: &Ident, strategy: LookupStrategy) -> syn::Result<TokenStream2> {
+        self.as_get_fn_with(name, strategy, |_| None)
+    }
+
+    /// Same as `as_get_fn`, see `as_consts_with`.
+    pub fn as_get_fn_with(
+        &self,
+        name: &Ident,
+        strategy: LookupStrategy,
+        ty_of: impl Fn(&Define<S>) -> Option<Type>,
+    ) -> syn::Result<TokenStream2> {
+        let (ty, mut entries) = self.table_entries(&ty_of)?;
+        let body = match strategy {
+            LookupStrategy::Match => {
+                let arms = entries.iter().map(|(key, value)| quote!(#key => Some(#value),));
+                quote! {
+                    match key {
+                        #(#arms)*
+                        _ => None,
+                    }
+                }
+            },
+            LookupStrategy::BinarySearch => {
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                let len = Literal::usize_unsuffixed(entries.len());
+                let entries = entries.iter().map(|(key, value)| quote!((#key, #value)));
+                quote! {
+                    static TABLE: [(&str, #ty); #len] = [#(#entries),*];
+                    TABLE
+                        .binary_search_by(|(k, _)| (*k).cmp(key))
+                        .ok()
+                        .map(|idx| TABLE[idx].1)
+                }
+            },
+        };
+        Ok(quote! {
+            pub fn #name(key: &str) -> Option<#ty> {
+                #body
+            }
+        })
+    }
+
+    /// The common type of the values, along with the keys and values of the
+    /// table.
+    fn table_entries(&self, ty_of: &impl Fn(&Define<S>) -> Option<Type>) -> syn::Result<TableEntries<'_, S>> {
+        let mut errors: Option<syn::Error> = None;
+        let mut common: Option<(Type, &Define<S>)> = None;
+        let mut entries: Vec<(String, &DefineValue<S>)> = Vec::with_capacity(self.vals.len());
+        for val in &self.vals {
+            let key = val.key();
+            if entries.iter().any(|(k, _)| *k == key) {
+                push_error(
+                    &mut errors,
+                    syn::Error::new(val.ident.span(), format!("Duplicate key `{}`", val.ident)),
+                );
+                continue;
+            }
+            entries.push((key, &val.value));
+            let ty = match ty_of(val).map_or_else(|| val.value.infer_type(), Ok) {
+                Ok(ty) => ty,
+                Err(e) => {
+                    push_error(&mut errors, e);
+                    continue;
+                },
+            };
+            match &common {
+                None => common = Some((ty, val)),
+                Some((common_ty, first)) => {
+                    if ty.to_token_stream().to_string() != common_ty.to_token_stream().to_string() {
+                        push_error(
+                            &mut errors,
+                            syn::Error::new_spanned(
+                                &val.value,
+                                format!(
+                                    "Expected every value to be `{}` like `{}`, but `{}` is `{}`",
+                                    common_ty.to_token_stream(),
+                                    first.ident,
+                                    val.ident,
+                                    ty.to_token_stream()
+                                ),
+                            ),
+                        );
+                    }
+                },
+            }
+        }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        match common {
+            Some((ty, _)) => Ok((ty, entries)),
+            None => Err(syn::Error::new(
+                Span::call_site(),
+                "Can't generate a table from an empty block of defines",
+            )),
+        }
+    }
+}
+
+/// e.g `WIDTH` for `width` and `BACKGROUND_COLOR` for `backgroundColor`.
+/// The common type of a table, and its keys and values.
+type TableEntries<'a, S> = (Type, Vec<(String, &'a DefineValue<S>)>);
+
+fn const_name<S: DefineSeparators>(define: &Define<S>) -> Ident {
+    let name = to_snake_case(&define.key()).to_uppercase();
+    let mut ident = format_ident!("{}", name);
+    ident.set_span(define.ident.span());
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::{format_ident, quote};
+
+    use super::LookupStrategy;
+    use crate::define::{Defines, KeyPolicy};
+
+    fn messages(result: syn::Result<crate::TokenStream2>) -> Vec<String> {
+        result.err().unwrap().into_iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn consts() {
+        let defines: Defines = syn::parse_str(
+            r#"width: 4, backgroundColor: "red", neg: -2.5, small: 3u8, list: [1, 2], c: 'x', b: true"#,
+        )
+        .unwrap();
+        let consts = defines.as_consts().unwrap();
+        assert_eq!(
+            consts.to_string(),
+            quote! {
+                pub const WIDTH: i32 = 4;
+                pub const BACKGROUND_COLOR: &'static str = "red";
+                pub const NEG: f64 = -2.5;
+                pub const SMALL: u8 = 3u8;
+                pub const LIST: [i32; 2] = [1, 2];
+                pub const C: char = 'x';
+                pub const B: bool = true;
+            }
+            .to_string()
+        );
+        syn::parse2::<syn::File>(consts).unwrap();
+
+        let defines: Defines = syn::parse_str(r#"a: 1, c: foo(), d: [1, "a"]"#).unwrap();
+        let consts = defines.as_consts_with(|d| match d.key().as_str() {
+            "c" => Some(syn::parse_quote!(Foo)),
+            _ => None,
+        });
+        assert_eq!(messages(consts), [
+            "Expected every item of the list to be `i32`, but found `& 'static str`"
+        ]);
+    }
+
+    #[test]
+    fn const_collisions() {
+        let defines: Defines =
+            syn::parse_str("width: 1, Width: 2, backgroundColor: 3, background_color: 4").unwrap();
+        let defines = defines.with_key_policy(KeyPolicy::Exact).unwrap();
+        let errors = messages(defines.as_consts());
+        assert_eq!(errors, [
+            "Key `Width` becomes the constant `WIDTH`, like `width`",
+            "`width` is first defined here",
+            "Key `background_color` becomes the constant `BACKGROUND_COLOR`, like `backgroundColor`",
+            "`backgroundColor` is first defined here",
+        ]);
+    }
+
+    #[test]
+    fn tables() {
+        let defines: Defines = syn::parse_str("width: 4, height: 8, depth: -1").unwrap();
+        let table = defines.as_static_table(&format_ident!("SIZES")).unwrap();
+        assert_eq!(
+            table.to_string(),
+            quote!(pub static SIZES: [(&str, i32); 3] = [("width", 4), ("height", 8), ("depth", -1)];)
+                .to_string()
+        );
+
+        let get = format_ident!("get");
+        let by_match = defines.as_get_fn(&get, LookupStrategy::Match).unwrap();
+        assert_eq!(
+            by_match.to_string(),
+            quote! {
+                pub fn get(key: &str) -> Option<i32> {
+                    match key {
+                        "width" => Some(4),
+                        "height" => Some(8),
+                        "depth" => Some(-1),
+                        _ => None,
+                    }
+                }
+            }
+            .to_string()
+        );
+        let by_search = defines.as_get_fn(&get, LookupStrategy::BinarySearch).unwrap();
+        assert!(by_search.to_string().contains(r#"[("depth" , - 1) , ("height" , 8) , ("width" , 4)]"#));
+        syn::parse2::<syn::File>(by_search).unwrap();
+
+        let defines: Defines = syn::parse_str(r#"a: 1, b: "x", c: foo()"#).unwrap();
+        let errors = messages(defines.as_get_fn(&get, LookupStrategy::Match));
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0], "Expected every value to be `i32` like `a`, but `b` is `& 'static str`");
+    }
+}
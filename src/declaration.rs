@@ -1,15 +1,18 @@
 use std::fmt::{Display, Formatter};
 
 use debug_helpers::debug_file;
+use derive_display::derive_display;
 use paste::paste;
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, ToTokens};
 use syn::__private::TokenStream2;
 use syn::parse::{Parse, ParseStream};
-use syn::{token, Token};
+use syn::punctuated::Punctuated;
+use syn::{braced, parse_quote, token, Block, Stmt, Token, Type, TypeParamBound, WherePredicate};
 
+use crate::node::Nodes;
 use crate::param::Params;
-use crate::parse_utils::parse_peekables_until;
+use crate::parse_utils::{parse_peekables_until, parse_until_peek};
 use crate::punct_set::PunctSet;
 use crate::{
     discard_next_token,
@@ -20,30 +23,130 @@ use crate::{
     EMPTY_STR,
 };
 
-pub type Trails<T: Parse = Declaration> = PunctSet<T, Token![+]>;
+pub type Trails<T: Parse = Trail> = PunctSet<T, Token![+]>;
 
-/// e.g:
+impl Trails {
+    /// All the trails as regular Rust bounds, e.g for `T: #bounds`.
+    pub fn as_bounds(&self) -> syn::Result<Punctuated<TypeParamBound, Token![+]>> {
+        let mut result = Punctuated::new();
+        for val in &self.vals {
+            result.push(val.to_bound()?);
+        }
+        Ok(result)
+    }
+
+    /// The trails as a where-clause predicate bounding the type `ty`, i.e
+    /// `#ty: #bounds`.
+    pub fn as_where_predicate(&self, ty: &Type) -> syn::Result<WherePredicate> {
+        let bounds = self.as_bounds()?;
+        Ok(parse_quote!(#ty: #bounds))
+    }
+}
+
+/// A single trail of a declaration, which is either any regular Rust bound, or
+/// the simpler dsl-specific form of a declaration (i.e a bare ident with
+/// optional params), e.g:
+/// ```text
+/// Iterator<Item = u8> + 'static + ?Sized + for<'a> Fn(&'a str) + ~const Foo
+/// TraitA + TraitB(a: u8)
 /// ```
+#[derive(Clone)]
+pub enum Trail {
+    Declaration(Declaration),
+    Bound(TypeParamBound),
+}
+
+impl Trail {
+    /// The trail as a regular Rust bound. Declarations that are also valid
+    /// bounds (e.g `Clone` or `Fn(u8)`) are converted, others (e.g
+    /// `Trait(a: u8 = 1)`) are an error.
+    pub fn to_bound(&self) -> syn::Result<TypeParamBound> {
+        match self {
+            Trail::Bound(bound) => Ok(bound.clone()),
+            Trail::Declaration(declaration) => {
+                syn::parse2(declaration.to_token_stream()).map_err(|_| {
+                    syn::Error::new_spanned(
+                        declaration,
+                        format!("The trail `{}` is not a valid trait bound", declaration),
+                    )
+                })
+            },
+        }
+    }
+
+    pub fn as_declaration(&self) -> Option<&Declaration> {
+        match self {
+            Trail::Declaration(declaration) => Some(declaration),
+            Trail::Bound(_) => None,
+        }
+    }
+}
+
+#[derive_display]
+impl ToTokens for Trail {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Trail::Declaration(declaration) => declaration.to_tokens(tokens),
+            Trail::Bound(bound) => bound.to_tokens(tokens),
+        }
+    }
+}
+
+impl Parse for Trail {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // NOTE: only a bare ident, optionally followed by parens, is parsed as
+        // a declaration (i.e when the trail ends right after them), everything
+        // else is left for syn
+        let fork = input.fork();
+        let is_declaration = fork.parse::<Ident>().is_ok() && {
+            if fork.peek(token::Paren) {
+                discard_next_token!(fork);
+            }
+            fork.is_empty() || fork.peek(Token![+])
+        };
+        if is_declaration {
+            return Ok(Trail::Declaration(input.parse()?));
+        }
+        let bound: TypeParamBound = input.parse()?;
+        debug_file!(bound, "Parsed a trail as a regular bound, value below:");
+        Ok(Trail::Bound(bound))
+    }
+}
+
+/// e.g:
+/// ```text
 /// SomeType(a: u8 = 1, b: f32): TraitA + TraitB + TraitC
 /// SomeType(X(y)): Foo + Bar
 /// Foo(bar)
 /// ```
-/// NOTE: Also allows for a braced body that is just returned as a single
-/// TokenStream2 ```
+/// NOTE: Also allows for a braced body, the contents of which are parsed as
+/// `B` (by default just a TokenStream2, but e.g `Nodes`, `Defines` or `Stmts`
+/// work just as well), while the params are parsed as `P`:
+/// ```text
 /// Foo(bar) {
 ///    // Something here
 /// }
 /// ```
+/// A declaration can also define a template on top of another declaration,
+/// see `template` for how these are applied (value params, as here, need
+/// `Args` as the params type):
+/// ```text
+/// PrimaryButton = Button(color: Blue, padding: 8)
+/// ```
 #[derive(Clone)]
-pub struct Declaration {
+pub struct Declaration<
+    B: Parse + ToTokens = TokenStream2,
+    P: Parse + ToTokens + Display = Params,
+> {
     pub name:   Option<Ident>,
     pub ty:     Ident,
-    pub params: Option<Params>,
+    pub params: Option<P>,
     pub trails: Option<Trails>,
-    pub body:   Option<TokenStream2>,
+    pub body:   Option<(token::Brace, B)>,
+    pub base:   Option<(token::Eq, Box<Declaration<B, P>>)>,
 }
 
-impl Declaration {
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> Declaration<B, P> {
     pub fn as_widget_build_block(&self) -> TokenStream2 {
         let ty = &self.ty;
         let params = &self.params;
@@ -51,20 +154,67 @@ impl Declaration {
             #ty::new(#params)
         }
     }
+
+    /// The span of the braced body, if there was one.
+    pub fn body_span(&self) -> Option<Span> {
+        self.body.as_ref().map(|(brace, _)| brace.span.join())
+    }
+}
+
+impl<P: Parse + ToTokens + Display> Declaration<TokenStream2, P> {
+    /// Parses the body of this declaration as child nodes, i.e nested
+    /// declarations and the `if`/`for`/`match` control flow around them.
+    pub fn children(&self) -> syn::Result<Nodes> {
+        match &self.body {
+            Some((_, body)) => syn::parse2(body.clone()),
+            None => Ok(Nodes::empty()),
+        }
+    }
+}
+
+impl<P: Parse + ToTokens + Display> Declaration<Stmts, P> {
+    /// The body as a regular `syn::Block`, braces (and their spans) included.
+    pub fn body_block(&self) -> Option<Block> {
+        self.body.as_ref().map(|(brace, stmts)| Block {
+            brace_token: *brace,
+            stmts:       stmts.0.clone(),
+        })
+    }
+}
+
+/// The statements of a Rust block, for using a plain block of code as the body
+/// of a `Declaration`. Note that `syn::Block` itself can't be used for this,
+/// since it expects to parse its braces by itself, which the `Declaration`
+/// already did (see `Declaration::body_block` for getting one back).
+#[derive(Clone)]
+pub struct Stmts(pub Vec<Stmt>);
+
+impl ToTokens for Stmts {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for stmt in &self.0 {
+            stmt.to_tokens(tokens);
+        }
+    }
+}
+
+impl Parse for Stmts {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Self(Block::parse_within(input)?))
+    }
 }
 
-impl Display for Declaration {
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> Display for Declaration<B, P> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
-            "{}{}{}{}",
+            "{}{}{}{}{}",
             if self.name.is_some() {
                 format!("{}: ", self.name.clone().unwrap())
             } else {
                 EMPTY_STR.clone()
             },
             self.ty.to_token_stream().to_string(),
-            if self.params.is_some() {
-                format!("({})", self.params.clone().unwrap())
+            if let Some(params) = &self.params {
+                format!("({})", params)
             } else {
                 EMPTY_STR.clone()
             },
@@ -73,16 +223,21 @@ impl Display for Declaration {
             } else {
                 EMPTY_STR.clone()
             },
+            if let Some((_, base)) = &self.base {
+                format!(" = {}", base)
+            } else {
+                EMPTY_STR.clone()
+            },
         ))
     }
 }
 
-impl ToTokens for Declaration {
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> ToTokens for Declaration<B, P> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let mut result = TokenStream2::new();
         let mut s = self;
         if let Some(v) = &s.name {
-            self.ty.to_tokens(&mut result);
+            v.to_tokens(&mut result);
             token::Colon::default().to_tokens(&mut result);
         }
         self.ty.to_tokens(&mut result);
@@ -93,14 +248,20 @@ impl ToTokens for Declaration {
             result.extend(token::Colon::default().to_token_stream());
             v.clone().to_tokens(&mut result);
         }
-        if let Some(v) = &s.body {
-            surround!(token::Brace, v, result);
+        if let Some((brace, body)) = &s.body {
+            brace.surround(&mut result, |inner| {
+                body.to_tokens(inner);
+            });
+        }
+        if let Some((eq, base)) = &s.base {
+            eq.to_tokens(&mut result);
+            base.to_tokens(&mut result);
         }
         result.to_tokens(tokens);
     }
 }
 
-impl Parse for Declaration {
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> Parse for Declaration<B, P> {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         unwrap_input!(input, unwrapped_input);
         let mut name: Option<Ident> = None;
@@ -108,11 +269,25 @@ impl Parse for Declaration {
             name = unwrapped_input.parse().ok();
             discard_next_token!(unwrapped_input);
         }
-        let ty: Ident = unwrapped_input
-            .parse()
-            .expect("Expected a Type for Declaration");
+        let ty: Ident = unwrapped_input.parse()?;
         debug_file!(!"Found ident, starting to parse Declaration for `{}`", ty);
-        try_get_tuple_params!(unwrapped_input, params, Params);
+        if unwrapped_input.peek(Token![=])
+            && !unwrapped_input.peek(Token![==])
+            && !unwrapped_input.peek(Token![=>])
+        {
+            let eq: Token![=] = unwrapped_input.parse()?;
+            let base: Declaration<B, P> = unwrapped_input.parse()?;
+            debug_file!(base, "Found a template base for the Declaration (below)");
+            return Ok(Self {
+                name,
+                ty,
+                params: None,
+                trails: None,
+                body: None,
+                base: Some((eq, Box::new(base))),
+            });
+        }
+        try_get_tuple_params!(unwrapped_input, params, P);
         try_get_trails!(unwrapped_input, trails);
         if trails.is_some() {
             debug_file!(
@@ -122,11 +297,15 @@ impl Parse for Declaration {
         } else {
             debug_file!("Found no trails for Declaration :-(");
         }
-        parse_peekables_until(unwrapped_input, token::Brace)?;
         let mut body = None;
-        if !unwrapped_input.is_empty() {
-            let fork = unwrapped_input.fork();
-            body = fork.parse().ok();
+        if unwrapped_input.peek(token::Brace) {
+            let content;
+            let brace = braced!(content in unwrapped_input);
+            let value: B = content.parse()?;
+            if !content.is_empty() {
+                return Err(content.error("Unexpected tokens at the end of the Declaration body"));
+            }
+            body = Some((brace, value));
         }
         Ok(Self {
             name,
@@ -134,6 +313,210 @@ impl Parse for Declaration {
             params,
             trails,
             body,
+            base: None,
         })
     }
 }
+
+/// A document of several declarations, separated by either `;`, `,` or `#`,
+/// e.g:
+/// ```text
+/// Header(title: String) { .. } #
+/// Body(a: u8): Clone { .. } #
+/// Footer
+/// ```
+/// Every item keeps the separator that followed it (if any) and its span, so
+/// that the whole document can be re-emitted as it was.
+#[derive(Clone)]
+pub struct Declarations<
+    B: Parse + ToTokens = TokenStream2,
+    P: Parse + ToTokens + Display = Params,
+> {
+    pub vals:  Vec<Declaration<B, P>>,
+    pub seps:  Vec<Option<DeclarationSeparator>>,
+    pub spans: Vec<Span>,
+}
+
+#[derive(Clone)]
+pub enum DeclarationSeparator {
+    Semi(Token![;]),
+    Comma(Token![,]),
+    Pound(Token![#]),
+}
+
+impl DeclarationSeparator {
+    pub fn peek(input: ParseStream) -> bool {
+        input.peek(Token![;]) || input.peek(Token![,]) || input.peek(Token![#])
+    }
+}
+
+impl ToTokens for DeclarationSeparator {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            DeclarationSeparator::Semi(t) => t.to_tokens(tokens),
+            DeclarationSeparator::Comma(t) => t.to_tokens(tokens),
+            DeclarationSeparator::Pound(t) => t.to_tokens(tokens),
+        }
+    }
+}
+
+impl Parse for DeclarationSeparator {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![;]) {
+            Ok(DeclarationSeparator::Semi(input.parse()?))
+        } else if input.peek(Token![,]) {
+            Ok(DeclarationSeparator::Comma(input.parse()?))
+        } else if input.peek(Token![#]) {
+            Ok(DeclarationSeparator::Pound(input.parse()?))
+        } else {
+            Err(input.error("Expected a `;`, `,` or `#` to separate declarations"))
+        }
+    }
+}
+
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> Declarations<B, P> {
+    pub fn empty() -> Self {
+        Declarations {
+            vals:  Vec::new(),
+            seps:  Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vals.is_empty()
+    }
+
+    /// Iterates the declarations together with the span each was parsed from.
+    pub fn iter_spanned(&self) -> impl Iterator<Item = (&Declaration<B, P>, Span)> {
+        self.vals.iter().zip(self.spans.iter().copied())
+    }
+}
+
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> ToTokens for Declarations<B, P> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for (val, sep) in self.vals.iter().zip(self.seps.iter()) {
+            val.to_tokens(tokens);
+            sep.to_tokens(tokens);
+        }
+    }
+}
+
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> Display for Declarations<B, P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}", self.to_token_stream()))
+    }
+}
+
+impl<B: Parse + ToTokens, P: Parse + ToTokens + Display> Parse for Declarations<B, P> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut result = Self::empty();
+        while !input.is_empty() {
+            let (tokens, span) = parse_until_peek(input, DeclarationSeparator::peek)?;
+            if tokens.is_empty() {
+                return Err(input.error("Expected a declaration before the separator"));
+            }
+            let val: Declaration<B, P> = syn::parse2(tokens)?;
+            debug_file!(val, "Parsed a Declaration of a document, value below:");
+            let sep: Option<DeclarationSeparator> = if input.is_empty() {
+                None
+            } else {
+                Some(input.parse()?)
+            };
+            result.vals.push(val);
+            result.seps.push(sep);
+            result.spans.push(span);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::ToTokens;
+
+    use super::{Declaration, Declarations, Stmts, Trail};
+    use crate::define::Defines;
+
+    #[test]
+    fn bodies() {
+        let stmts: Declaration<Stmts> = syn::parse_str("Foo(a: u8) { let b = a; b }").unwrap();
+        assert_eq!(stmts.body_block().unwrap().stmts.len(), 2);
+        assert!(stmts.body_span().is_some());
+
+        let defines: Declaration<Defines> = syn::parse_str("Foo { a: 1, b: 2 }").unwrap();
+        assert_eq!(defines.body.unwrap().1.len(), 2);
+
+        let tokens: Declaration = syn::parse_str("Foo(a: u8): Clone { anything here }").unwrap();
+        let again: Declaration = syn::parse2(tokens.to_token_stream()).unwrap();
+        assert_eq!(again.to_string(), tokens.to_string());
+    }
+
+    #[test]
+    fn bodies_are_consumed() {
+        let doc: Declarations<Stmts> = syn::parse_str("Foo { let a = 1; }; Bar").unwrap();
+        assert_eq!(doc.len(), 2);
+        assert!(syn::parse_str::<Declaration<Stmts>>("Foo { let a = 1; } extra").is_err());
+    }
+
+    #[test]
+    fn documents() {
+        let doc: Declarations = syn::parse_str(
+            "Header(title: String) { a } #
+            Body(a: u8): Clone { b };
+            Footer, Extra",
+        )
+        .unwrap();
+        let types: Vec<String> = doc.vals.iter().map(|v| v.ty.to_string()).collect();
+        assert_eq!(types, ["Header", "Body", "Footer", "Extra"]);
+        assert_eq!(doc.seps.iter().filter(|s| s.is_some()).count(), 3);
+        assert_eq!(doc.iter_spanned().count(), 4);
+        let again: Declarations = syn::parse2(doc.to_token_stream()).unwrap();
+        assert_eq!(again.to_string(), doc.to_string());
+        assert!(syn::parse_str::<Declarations>("Foo;; Bar").is_err());
+    }
+
+    #[test]
+    fn trails() {
+        let declaration: Declaration = syn::parse_str(
+            "Foo(a: u8): Iterator<Item = u8> + 'static + ?Sized + for<'a> Fn(&'a str) + ~const Bar \
+             + Dsl(a: u8 = 1) { x }",
+        )
+        .unwrap();
+        let trails = declaration.trails.unwrap();
+        assert_eq!(trails.len(), 6);
+        assert!(trails.vals[..5].iter().all(|t| matches!(t, Trail::Bound(_))));
+        assert!(trails.vals[5].as_declaration().is_some());
+        assert!(declaration.body.is_some());
+        let err = trails.as_bounds().err().unwrap();
+        assert!(err.to_string().contains("is not a valid trait bound"), "{}", err);
+
+        let declaration: Declaration = syn::parse_str("Foo(a: u8): Clone + Debug + Dsl(a: u8 = 1)").unwrap();
+        let trails = declaration.trails.unwrap();
+        assert!(trails.iter().all(|t| t.as_declaration().is_some()));
+        assert_eq!(trails.vals[0].to_bound().unwrap().to_token_stream().to_string(), "Clone");
+
+        let declaration: Declaration =
+            syn::parse_str("Foo(a: u8): Iterator<Item = u8> + 'static + Fn(u8)").unwrap();
+        let predicate = declaration
+            .trails
+            .unwrap()
+            .as_where_predicate(&syn::parse_quote!(T))
+            .unwrap();
+        assert_eq!(
+            predicate.to_token_stream().to_string(),
+            "T : Iterator < Item = u8 > + 'static + Fn (u8)"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(syn::parse_str::<Declaration>("Foo(a: ,)").is_err());
+        assert!(syn::parse_str::<Declaration>("\"text\"").is_err());
+        assert!(syn::parse_str::<Declarations>("1; Foo").is_err());
+    }
+}
@@ -1,22 +1,402 @@
+use std::cell::Cell;
 use std::fmt::{Display, Formatter};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
 
 use debug_helpers::debug_file;
-use derive_display::derive_display;
-use proc_macro2::{Ident, TokenStream, TokenTree};
-use quote::{quote, ToTokens};
-use syn::parse::{Parse, ParseStream};
-use syn::{token, Token};
+use proc_macro2::{Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+use quote::{quote, ToTokens, TokenStreamExt};
+use syn::buffer::Cursor;
+use syn::parse::discouraged::Speculative;
+use syn::parse::{Parse, ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::{
+    braced,
+    bracketed,
+    token,
+    Block,
+    Expr,
+    ExprArray,
+    ExprBlock,
+    ExprLit,
+    ExprPath,
+    ExprUnary,
+    Lit,
+    LitStr,
+    Path,
+    Token,
+    Type,
+    TypePath,
+    UnOp,
+};
 
+use crate::parse_utils::{peek_delim, push_error, skip_until_peek, Angles};
 use crate::punct_set::PunctSet;
+use crate::suggest::did_you_mean_suffix;
 use crate::TokenStream2;
 
-pub type Defines = PunctSet<Define, Token![,]>;
+/// A comma separated block of `Define`s, e.g:
+/// ```text
+/// width: 4, Height: 8, label: "Foo"
+/// ```
+/// Values may themselves be blocks of defines (or lists of them), which are
+/// looked up with dotted paths, see `get_path`.
+/// How keys are matched on lookups (`get`, `require`, `take`...) depends on
+/// the `KeyPolicy` of the block, which is `KeyPolicy::CaseInsensitive` unless
+/// chosen otherwise with `with_key_policy`.
+#[derive(Clone)]
+pub struct Defines<S: DefineSeparators = ColonSeparated> {
+    pub set:        PunctSet<Define<S>, token::Comma>,
+    pub key_policy: KeyPolicy,
+}
+
+impl<S: DefineSeparators> Deref for Defines<S> {
+    type Target = PunctSet<Define<S>, token::Comma>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.set
+    }
+}
+
+impl<S: DefineSeparators> DerefMut for Defines<S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.set
+    }
+}
+
+impl<S: DefineSeparators> Display for Defines<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_token_stream().to_string())
+    }
+}
+
+impl<S: DefineSeparators> ToTokens for Defines<S> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.set.to_tokens(tokens);
+    }
+}
+
+impl<S: DefineSeparators> Parse for Defines<S> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Ok(Defines {
+            set:        input.parse()?,
+            key_policy: KeyPolicy::default(),
+        })
+    }
+}
 
-impl Defines {
+impl<S: DefineSeparators> Defines<S> {
     pub fn empty() -> Self {
         Defines {
-            vals:  Vec::new(),
-            delim: Some(token::Comma::default()),
+            set:        PunctSet::empty(),
+            key_policy: KeyPolicy::default(),
+        }
+    }
+
+    /// Switches the block (and every block nested in it) to the given key
+    /// policy, erroring on every pair of keys that would be considered the
+    /// same key by it.
+    pub fn with_key_policy(mut self, key_policy: KeyPolicy) -> syn::Result<Self> {
+        let mut errors: Option<syn::Error> = None;
+        for val in self.set.vals.iter_mut() {
+            if let Err(e) = val.value.set_key_policy(key_policy) {
+                push_error(&mut errors, e);
+            }
+        }
+        self.key_policy = key_policy;
+        if let Err(e) = self.check_collisions() {
+            push_error(&mut errors, e);
+        }
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+
+    /// The key of the define, as normalized by the key policy of this block.
+    pub fn key_of(&self, define: &Define<S>) -> String {
+        self.key_policy.normalize(&define.key())
+    }
+
+    fn matching<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a Define<S>> {
+        let key = self.key_policy.normalize(key);
+        self.vals.iter().filter(move |v| self.key_policy.normalize(&v.key()) == key)
+    }
+
+    /// Errors on every key that collides with an earlier key of the block,
+    /// i.e the two normalize to the same key with the current key policy.
+    pub fn check_collisions(&self) -> syn::Result<()> {
+        let mut errors: Option<syn::Error> = None;
+        for (idx, val) in self.vals.iter().enumerate() {
+            let key = self.key_of(val);
+            let first = self.vals[..idx].iter().find(|v| self.key_of(v) == key);
+            if let Some(first) = first {
+                push_error(&mut errors, self.collision_error(first, val));
+            }
+        }
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn collision_error(&self, first: &Define<S>, val: &Define<S>) -> syn::Error {
+        let msg = if first.ident == val.ident {
+            format!("Duplicate key `{}`", val.ident)
+        } else {
+            format!(
+                "Key `{}` collides with `{}` ({} key matching)",
+                val.ident,
+                first.ident,
+                self.key_policy.describe()
+            )
+        };
+        syn::Error::new(val.ident.span(), msg)
+    }
+
+    /// Looks up the define for `key`, marking it as consumed (see
+    /// `deny_unknown`). Errors when more than one key of the block matches, e.g
+    /// `Width` and `width` with case-insensitive key matching, see
+    /// `check_collisions`.
+    pub fn get(&self, key: &str) -> syn::Result<Option<&Define<S>>> {
+        let mut result: Option<&Define<S>> = None;
+        let mut errors: Option<syn::Error> = None;
+        for val in self.matching(key) {
+            val.mark_used();
+            match result {
+                Some(first) => push_error(&mut errors, self.collision_error(first, val)),
+                None => result = Some(val),
+            }
+        }
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
+    }
+
+    /// Same as `get`, but a missing key is an error, spanned at the block.
+    pub fn require(&self, key: &str) -> syn::Result<&Define<S>> {
+        self.get(key)?.ok_or_else(|| {
+            syn::Error::new(self.set.span(), format!("Missing required key `{}`", key))
+        })
+    }
+
+    /// Removes the define for `key` from the set and returns it, marked as
+    /// consumed. Errors on ambiguous keys like `get`, without removing any.
+    pub fn take(&mut self, key: &str) -> syn::Result<Option<Define<S>>> {
+        let normalized = self.key_policy.normalize(key);
+        let mut found = self
+            .vals
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| self.key_policy.normalize(&v.key()) == normalized)
+            .map(|(idx, _)| idx);
+        let idx = match found.next() {
+            Some(idx) => idx,
+            None => return Ok(None),
+        };
+        let mut errors: Option<syn::Error> = None;
+        for other in found {
+            push_error(&mut errors, self.collision_error(&self.vals[idx], &self.vals[other]));
+        }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        let result = self.set.remove(idx);
+        result.mark_used();
+        Ok(Some(result))
+    }
+
+    /// Looks up `key` and converts its value, see `FromDefineValue`.
+    pub fn get_as<T: FromDefineValue<S>>(&self, key: &str) -> syn::Result<Option<T>> {
+        match self.get(key)? {
+            Some(define) => Ok(Some(T::from_define_value(&define.value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Same as `get_as`, but a missing key is an error.
+    pub fn require_as<T: FromDefineValue<S>>(&self, key: &str) -> syn::Result<T> {
+        T::from_define_value(&self.require(key)?.value)
+    }
+
+    /// Same as `get_as`, but falls back to `default` for a missing key.
+    pub fn get_or<T: FromDefineValue<S>>(&self, key: &str, default: T) -> syn::Result<T> {
+        Ok(self.get_as(key)?.unwrap_or(default))
+    }
+
+    /// Looks up a value through the nested blocks of defines (and lists, by
+    /// index), e.g `theme.colors.primary` or `theme.fonts.0` in:
+    /// ```text
+    /// theme: { colors: { primary: "#fff", accent: Red }, fonts: ["Inter"] }
+    /// ```
+    /// Every define along the path is marked as consumed.
+    pub fn get_path(&self, path: &str) -> Option<&DefineValue<S>> {
+        self.require_path(path).ok()
+    }
+
+    /// Same as `get_path`, but a missing key is an error, spanned at the
+    /// block (or list) it is missing from.
+    pub fn require_path(&self, path: &str) -> syn::Result<&DefineValue<S>> {
+        let mut segments = path.split('.');
+        let first = segments.next().unwrap_or_default();
+        let mut value = &self.require(first)?.value;
+        let mut walked = first.to_string();
+        for segment in segments {
+            value = match value {
+                DefineValue::Defines(brace, defines) => match defines.get(segment)? {
+                    Some(define) => &define.value,
+                    None => {
+                        let keys: Vec<String> = defines.vals.iter().map(|v| v.key()).collect();
+                        let suggestion = did_you_mean_suffix(segment, keys.iter().map(|k| k.as_str()));
+                        return Err(syn::Error::new(
+                            brace.span.join(),
+                            format!("Missing key `{}` in `{}`{}", segment, walked, suggestion),
+                        ));
+                    },
+                },
+                DefineValue::List(bracket, vals) => {
+                    match segment.parse::<usize>().ok().and_then(|idx| vals.iter().nth(idx)) {
+                        Some(val) => val,
+                        None => {
+                            return Err(syn::Error::new(
+                                bracket.span.join(),
+                                format!(
+                                    "No item `{}` in `{}`, which is a list of {} items",
+                                    segment,
+                                    walked,
+                                    vals.len()
+                                ),
+                            ));
+                        },
+                    }
+                },
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        value,
+                        format!(
+                            "Expected a block of defines or a list at `{}`, but found {}",
+                            walked,
+                            value.describe()
+                        ),
+                    ));
+                },
+            };
+            walked.push('.');
+            walked.push_str(segment);
+        }
+        Ok(value)
+    }
+
+    /// Same as `get_path`, but converts the value, see `FromDefineValue`.
+    pub fn get_path_as<T: FromDefineValue<S>>(&self, path: &str) -> syn::Result<Option<T>> {
+        match self.get_path(path) {
+            Some(value) => Ok(Some(T::from_define_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Merges the sections (i.e nested blocks of defines) defined more than
+    /// once into the first of them, recursively, e.g:
+    /// ```text
+    /// theme: { colors: { primary: "#fff" } },
+    /// theme: { colors: { accent: Red }, spacing: 4 }
+    /// ```
+    /// becomes `theme: { colors: { primary: "#fff", accent: Red }, spacing: 4 }`.
+    /// A merged section keeps the key and the braces of its first definition,
+    /// so errors still point at it. Any other key defined more than once is an
+    /// error.
+    pub fn merge_sections(&self) -> syn::Result<Self> {
+        let mut result = Defines {
+            set:        PunctSet::empty(),
+            key_policy: self.key_policy,
+        };
+        let mut errors: Option<syn::Error> = None;
+        for (val_idx, val) in self.vals.iter().enumerate() {
+            let key = self.key_of(val);
+            let existing = result.vals.iter().position(|v| result.key_of(v) == key);
+            let idx = match existing {
+                Some(idx) => idx,
+                None => {
+                    let mut val = val.clone();
+                    if let DefineValue::Defines(_, section) = &mut val.value {
+                        match section.merge_sections() {
+                            Ok(merged) => *section = merged,
+                            Err(e) => push_error(&mut errors, e),
+                        }
+                    }
+                    let delim = self.delims.get(val_idx).cloned().flatten();
+                    let span = self.span_of(val_idx).unwrap_or_else(Span::call_site);
+                    result.set.push_spanned(val, delim, span);
+                    continue;
+                },
+            };
+            let first = &mut result.set.vals[idx];
+            match (&mut first.value, &val.value) {
+                (DefineValue::Defines(_, section), DefineValue::Defines(_, other)) => {
+                    let mut combined = section.clone();
+                    for (idx, other_val) in other.vals.iter().enumerate() {
+                        let delim = other.delims.get(idx).cloned().flatten();
+                        let span = other.span_of(idx).unwrap_or_else(Span::call_site);
+                        combined.set.push_spanned(other_val.clone(), delim, span);
+                    }
+                    match combined.merge_sections() {
+                        Ok(merged) => *section = merged,
+                        Err(e) => push_error(&mut errors, e),
+                    }
+                },
+                _ => {
+                    let mut e = syn::Error::new(val.ident.span(), format!("Duplicate key `{}`", val.ident));
+                    e.combine(syn::Error::new(
+                        first.ident.span(),
+                        format!("`{}` is first defined here", first.ident),
+                    ));
+                    push_error(&mut errors, e);
+                },
+            }
+        }
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> syn::Result<Option<String>> {
+        self.get_as(key)
+    }
+
+    pub fn get_bool(&self, key: &str) -> syn::Result<Option<bool>> {
+        self.get_as(key)
+    }
+
+    /// Errors on every define that was not consumed through any of the
+    /// lookups above, all combined into a single error. Meant to be called
+    /// once all the known keys have been extracted.
+    ///
+    /// The nested blocks of consumed defines (and of their lists) are checked
+    /// too, and their keys reported by their dotted path, e.g
+    /// `theme.colors.extra` (see `get_path`).
+    pub fn deny_unknown(&self) -> syn::Result<()> {
+        let mut errors: Option<syn::Error> = None;
+        self.collect_unknown("", &mut errors);
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn collect_unknown(&self, prefix: &str, errors: &mut Option<syn::Error>) {
+        for val in &self.vals {
+            let path = format!("{}{}", prefix, val.ident);
+            if val.used.get() {
+                val.value.collect_unknown(&path, errors);
+            } else {
+                push_error(
+                    errors,
+                    syn::Error::new(val.ident.span(), format!("Unknown key `{}`", path)),
+                );
+            }
         }
     }
 }
@@ -25,7 +405,7 @@ impl Defines {
 /// * `field_name` : `field_type`
 ///
 /// E.g:
-/// ```
+/// ```text
 /// foo: Bar
 /// ```
 /// Alternatively, it can be used to represent simple value assignments in
@@ -33,53 +413,1026 @@ impl Defines {
 /// * `var_name` : `var_value`
 ///
 /// E.g:
-/// ```
+/// ```text
 /// thing: SomeCtor(cool)
 /// ```
+/// The value is classified on parse, see `DefineValue`. Which separators are
+/// accepted between the key and the value (`:` by default) depends on `S`, see
+/// `DefineSeparators`.
 #[derive(Clone)]
-pub struct Define {
-    pub ident: Ident,
-    pub value: TokenStream2,
+pub struct Define<S: DefineSeparators = ColonSeparated> {
+    pub ident:     Ident,
+    /// The separator as written, which is also the one emitted back.
+    pub separator: DefineSeparator,
+    pub value:     DefineValue<S>,
+    used:          Cell<bool>,
+    separators:    PhantomData<S>,
 }
 
-impl Define {
+impl<S: DefineSeparators> Define<S> {
+    /// A define with the default separator of `S`.
+    pub fn new(ident: Ident, value: DefineValue<S>) -> Self {
+        Define {
+            ident,
+            separator: DefineSeparator::new(S::ALLOWED[0]),
+            value,
+            used: Cell::new(false),
+            separators: PhantomData,
+        }
+    }
+
+    /// Whether this define was consumed by any of the `Defines` lookups.
+    pub fn is_used(&self) -> bool {
+        self.used.get()
+    }
+
+    pub fn mark_used(&self) {
+        self.used.set(true);
+    }
+
+    /// The key in lowercase, i.e as matched with `KeyPolicy::CaseInsensitive`.
     pub fn name(&self) -> String {
         self.ident.to_string().to_lowercase()
     }
 
+    /// The key exactly as written (minus a possible `r#` prefix).
+    pub fn key(&self) -> String {
+        let key = self.ident.to_string();
+        match key.strip_prefix("r#") {
+            Some(stripped) => stripped.to_string(),
+            None => key,
+        }
+    }
+
     pub fn as_pub(&self) -> TokenStream2 {
         let s = self;
         quote!(pub #s)
     }
 }
 
-#[derive_display]
-impl ToTokens for Define {
+impl<S: DefineSeparators> Display for Define<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_token_stream().to_string())
+    }
+}
+
+impl<S: DefineSeparators> ToTokens for Define<S> {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let ident = &self.ident;
+        let separator = &self.separator;
         let value = &self.value;
         let q = quote! {
-            #ident: #value
+            #ident #separator #value
         };
         q.to_tokens(tokens);
     }
 }
 
-impl Parse for Define {
+impl<S: DefineSeparators> Parse for Define<S> {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let ident: Ident = input.parse().ok().expect("Expected an ident");
-        let _ = input
-            .parse::<TokenTree>()
-            .expect("Expected a `:` to separate ident and value");
-        let value: TokenStream2 = input.parse().ok().expect(
-            "Expected a value after the \
-        ident",
-        );
-        let result = Self {
+        let ident: Ident = input.parse()?;
+        let separator = DefineSeparator::parse_allowed(input, S::ALLOWED, &ident)?;
+        if input.is_empty() || input.peek(Token![,]) {
+            return Err(input.error(format!("Expected a value after `{}`", ident)));
+        }
+        let value: DefineValue<S> = input.parse()?;
+        let result = Define {
             ident,
+            separator,
             value,
+            used: Cell::new(false),
+            separators: PhantomData,
         };
         debug_file!(result, "Finishing parsin Define, value below:");
         Ok(result)
     }
 }
+
+/// How the keys of a `Defines` block are matched against each other and
+/// against the keys looked up.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum KeyPolicy {
+    /// `width` only matches `width`.
+    Exact,
+    /// `width` matches `Width` and `WIDTH`.
+    #[default]
+    CaseInsensitive,
+    /// Keys are compared in snake_case, so `background_color` matches
+    /// `backgroundColor` and `BackgroundColor` (but not `backgroundcolor`).
+    Normalized,
+}
+
+impl KeyPolicy {
+    pub fn normalize(&self, key: &str) -> String {
+        match self {
+            KeyPolicy::Exact => key.to_string(),
+            KeyPolicy::CaseInsensitive => key.to_lowercase(),
+            KeyPolicy::Normalized => to_snake_case(key),
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            KeyPolicy::Exact => "exact",
+            KeyPolicy::CaseInsensitive => "case-insensitive",
+            KeyPolicy::Normalized => "snake/camel case normalized",
+        }
+    }
+}
+
+/// e.g `backgroundColor`, `BackgroundColor` and `background_color` all become
+/// `background_color`, and `HTTPServer` becomes `http_server`.
+pub fn to_snake_case(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    let mut result = String::with_capacity(key.len() + 4);
+    for (idx, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            let prev = chars[idx - 1];
+            let next_is_lower = chars.get(idx + 1).is_some_and(|n| n.is_lowercase());
+            if prev != '_' && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower)) {
+                result.push('_');
+            }
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
+
+/// The separators a `Define` may use between its key and its value.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SeparatorKind {
+    /// `key: value`
+    Colon,
+    /// `key = value`
+    Eq,
+    /// `key => value`
+    FatArrow,
+    /// `key := value`
+    ColonEq,
+}
+
+impl SeparatorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeparatorKind::Colon => ":",
+            SeparatorKind::Eq => "=",
+            SeparatorKind::FatArrow => "=>",
+            SeparatorKind::ColonEq => ":=",
+        }
+    }
+
+    /// The separator at the start of the input, if any. Longer separators are
+    /// matched first, so `=>` and `:=` are never mistaken for `=` and `:`.
+    pub fn peek(input: ParseStream) -> Option<Self> {
+        if input.peek(Token![=>]) {
+            Some(SeparatorKind::FatArrow)
+        } else if input.peek(Token![:]) && input.peek2(Token![=]) {
+            Some(SeparatorKind::ColonEq)
+        } else if input.peek(Token![:]) {
+            Some(SeparatorKind::Colon)
+        } else if input.peek(Token![=]) {
+            Some(SeparatorKind::Eq)
+        } else {
+            None
+        }
+    }
+
+    /// e.g "`:`" or "one of `:`, `=`", for error messages.
+    pub fn describe_all(kinds: &[SeparatorKind]) -> String {
+        let quoted = kinds
+            .iter()
+            .map(|k| format!("`{}`", k.as_str()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if kinds.len() == 1 {
+            quoted
+        } else {
+            format!("one of {}", quoted)
+        }
+    }
+}
+
+/// The separator of a `Define`, as written.
+#[derive(Clone)]
+pub enum DefineSeparator {
+    Colon(Token![:]),
+    Eq(Token![=]),
+    FatArrow(Token![=>]),
+    ColonEq(Token![:], Token![=]),
+}
+
+impl DefineSeparator {
+    pub fn new(kind: SeparatorKind) -> Self {
+        match kind {
+            SeparatorKind::Colon => DefineSeparator::Colon(Default::default()),
+            SeparatorKind::Eq => DefineSeparator::Eq(Default::default()),
+            SeparatorKind::FatArrow => DefineSeparator::FatArrow(Default::default()),
+            SeparatorKind::ColonEq => DefineSeparator::ColonEq(Default::default(), Default::default()),
+        }
+    }
+
+    pub fn kind(&self) -> SeparatorKind {
+        match self {
+            DefineSeparator::Colon(_) => SeparatorKind::Colon,
+            DefineSeparator::Eq(_) => SeparatorKind::Eq,
+            DefineSeparator::FatArrow(_) => SeparatorKind::FatArrow,
+            DefineSeparator::ColonEq(..) => SeparatorKind::ColonEq,
+        }
+    }
+
+    /// Parses the separator after the key `ident`, erroring (at the offending
+    /// token) when it isn't one of the `allowed` ones.
+    pub fn parse_allowed(input: ParseStream, allowed: &[SeparatorKind], ident: &Ident) -> syn::Result<Self> {
+        let kind = match SeparatorKind::peek(input) {
+            Some(kind) if allowed.contains(&kind) => kind,
+            found => {
+                let found = match found {
+                    Some(kind) => format!(", but found `{}`", kind.as_str()),
+                    None => String::new(),
+                };
+                return Err(input.error(format!(
+                    "Expected {} to separate `{}` and its value{}",
+                    SeparatorKind::describe_all(allowed),
+                    ident,
+                    found
+                )));
+            },
+        };
+        Ok(match kind {
+            SeparatorKind::Colon => DefineSeparator::Colon(input.parse()?),
+            SeparatorKind::Eq => DefineSeparator::Eq(input.parse()?),
+            SeparatorKind::FatArrow => DefineSeparator::FatArrow(input.parse()?),
+            SeparatorKind::ColonEq => DefineSeparator::ColonEq(input.parse()?, input.parse()?),
+        })
+    }
+}
+
+impl ToTokens for DefineSeparator {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            DefineSeparator::Colon(v) => v.to_tokens(tokens),
+            DefineSeparator::Eq(v) => v.to_tokens(tokens),
+            DefineSeparator::FatArrow(v) => v.to_tokens(tokens),
+            DefineSeparator::ColonEq(colon, eq) => {
+                // Joint, so it is emitted as `:=` rather than `: =`
+                let mut joint = Punct::new(':', Spacing::Joint);
+                joint.set_span(colon.span);
+                tokens.append(joint);
+                eq.to_tokens(tokens);
+            },
+        }
+    }
+}
+
+/// The separators accepted between the keys and values of a `Defines` type,
+/// e.g `Defines<EqSeparated>` parses:
+/// ```text
+/// width = 4, height = 8
+/// ```
+/// Nested blocks of defines accept the same separators as their parent.
+pub trait DefineSeparators: Clone + 'static {
+    /// The first one is used for the defines that weren't parsed, e.g the
+    /// defaults added by `DefinesSchema::validate`.
+    const ALLOWED: &'static [SeparatorKind];
+}
+
+macro_rules! define_separators {
+    ($($(#[$attr:meta])* $name:ident => [$($kind:ident),*]),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, Copy, Default, Debug)]
+            pub struct $name;
+
+            impl DefineSeparators for $name {
+                const ALLOWED: &'static [SeparatorKind] = &[$(SeparatorKind::$kind),*];
+            }
+        )*
+    };
+}
+
+define_separators! {
+    /// `key: value`, the default.
+    ColonSeparated => [Colon],
+    /// `key = value`
+    EqSeparated => [Eq],
+    /// `key => value`
+    FatArrowSeparated => [FatArrow],
+    /// Any of `key: value`, `key = value`, `key => value` and `key := value`.
+    AnySeparated => [Colon, Eq, FatArrow, ColonEq],
+}
+
+/// The value of a `Define`, classified by what it parses as (in this order):
+/// ```text
+/// a: { b: 1, c: 2 }     // Defines, i.e a nested block of defines (using a separator of `S`)
+/// a: {}                 // Defines, but empty
+/// a: { let b = 1; b }   // Block
+/// a: [1, 2, 3]          // List, e.g also `[{ b: 1 }, { b: 2 }]`
+/// a: "str"              // Lit
+/// a: some::path         // Path, without generic args
+/// a: 1 + foo(2)         // Expr, e.g also `Vec::<u8>::new`
+/// a: Vec<u8>            // Type
+/// a: Foo(b: u8) -> Bar  // Verbatim, i.e anything else
+/// ```
+/// The value ends at the first top-level `,`. Since many values are valid as
+/// more than one kind (e.g `&str` is both an expression and a type), the
+/// accessors (`as_expr`, `as_type`, `as_path`...) accept any kind that can be
+/// interpreted as what was asked for, and only error when it can't.
+#[derive(Clone)]
+pub enum DefineValue<S: DefineSeparators = ColonSeparated> {
+    Lit(Lit),
+    Path(Path),
+    Expr(Expr),
+    Type(Type),
+    Block(Block),
+    Defines(token::Brace, Defines<S>),
+    List(token::Bracket, Punctuated<DefineValue<S>, token::Comma>),
+    Verbatim(TokenStream2),
+}
+
+impl<S: DefineSeparators> DefineValue<S> {
+    /// Classifies the given tokens, which should contain just the value.
+    pub fn classify(tokens: TokenStream2) -> syn::Result<Self> {
+        let parser = |input: ParseStream| {
+            let end = input.fork();
+            while !end.is_empty() {
+                end.parse::<TokenTree>()?;
+            }
+            Self::classify_up_to(input, end.cursor())
+        };
+        parser.parse2(tokens)
+    }
+
+    /// Classifies the value from the input up to `end` (a later cursor of it),
+    /// trying every kind on a fork of the input, and keeping the first one that
+    /// ends right at `end`.
+    fn classify_up_to(input: ParseStream, end: Cursor) -> syn::Result<Self> {
+        if input.peek(token::Brace) {
+            let fork = input.fork();
+            let content;
+            let brace = braced!(content in fork);
+            if fork.cursor() == end && is_defines::<S>(&content) {
+                let defines: Defines<S> = content.parse()?;
+                if !content.is_empty() {
+                    return Err(content.error("unexpected token"));
+                }
+                input.advance_to(&fork);
+                return Ok(DefineValue::Defines(brace, defines));
+            }
+            if let Some(block) = parse_exact(input, end, |_: &Block| true) {
+                return Ok(DefineValue::Block(block));
+            }
+        }
+        if input.peek(token::Bracket) {
+            let fork = input.fork();
+            let content;
+            let bracket = bracketed!(content in fork);
+            if fork.cursor() == end {
+                let vals = Punctuated::parse_terminated(&content)?;
+                input.advance_to(&fork);
+                return Ok(DefineValue::List(bracket, vals));
+            }
+        }
+        if let Some(lit) = parse_exact(input, end, |_: &Lit| true) {
+            return Ok(DefineValue::Lit(lit));
+        }
+        // NOTE: paths with generic args (e.g `Vec<u8>`) are left for the
+        // expressions (`Vec::<u8>::new`) and the types
+        let no_generic_args = |path: &Path| path.segments.iter().all(|s| s.arguments.is_none());
+        if let Some(path) = parse_exact(input, end, no_generic_args) {
+            return Ok(DefineValue::Path(path));
+        }
+        if let Some(expr) = parse_exact(input, end, |_: &Expr| true) {
+            return Ok(DefineValue::Expr(expr));
+        }
+        if let Some(ty) = parse_exact(input, end, |_: &Type| true) {
+            return Ok(DefineValue::Type(ty));
+        }
+        let mut tokens = TokenStream2::new();
+        while input.cursor() != end {
+            tokens.extend(Some(input.parse::<TokenTree>()?));
+        }
+        Ok(DefineValue::Verbatim(tokens))
+    }
+
+    /// A description of the kind of value, for error messages.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            DefineValue::Lit(Lit::Str(_)) => "a string literal",
+            DefineValue::Lit(Lit::Int(_)) => "an integer literal",
+            DefineValue::Lit(Lit::Float(_)) => "a float literal",
+            DefineValue::Lit(Lit::Bool(_)) => "a boolean literal",
+            DefineValue::Lit(_) => "a literal",
+            DefineValue::Path(_) => "a path",
+            DefineValue::Expr(_) => "an expression",
+            DefineValue::Type(_) => "a type",
+            DefineValue::Block(_) => "a block",
+            DefineValue::Defines(..) => "a block of defines",
+            DefineValue::List(..) => "a list",
+            DefineValue::Verbatim(_) => "an unrecognized value",
+        }
+    }
+
+    fn error(&self, expected: &str) -> syn::Error {
+        syn::Error::new_spanned(
+            self,
+            format!("Expected {}, but found {}", expected, self.describe()),
+        )
+    }
+
+    pub fn as_lit(&self) -> syn::Result<&Lit> {
+        match self {
+            DefineValue::Lit(lit) => Ok(lit),
+            _ => Err(self.error("a literal")),
+        }
+    }
+
+    pub fn as_lit_str(&self) -> syn::Result<&LitStr> {
+        match self {
+            DefineValue::Lit(Lit::Str(lit)) => Ok(lit),
+            _ => Err(self.error("a string literal")),
+        }
+    }
+
+    /// The value of a string literal.
+    pub fn as_str(&self) -> syn::Result<String> {
+        Ok(self.as_lit_str()?.value())
+    }
+
+    pub fn as_bool(&self) -> syn::Result<bool> {
+        match self {
+            DefineValue::Lit(Lit::Bool(lit)) => Ok(lit.value),
+            _ => Err(self.error("a boolean literal")),
+        }
+    }
+
+    /// The value of an integer literal (which may also be negated), parsed as
+    /// `T`.
+    pub fn as_int<T: FromStr>(&self) -> syn::Result<T>
+    where
+        T::Err: Display,
+    {
+        match self {
+            DefineValue::Lit(Lit::Int(lit)) => lit.base10_parse(),
+            DefineValue::Expr(Expr::Unary(ExprUnary {
+                op: UnOp::Neg(_),
+                expr,
+                ..
+            })) => match expr.as_ref() {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(lit),
+                    ..
+                }) => format!("-{}", lit.base10_digits())
+                    .parse()
+                    .map_err(|e| syn::Error::new_spanned(self, e)),
+                _ => Err(self.error("an integer literal")),
+            },
+            _ => Err(self.error("an integer literal")),
+        }
+    }
+
+    /// The value of a float (or integer) literal, which may also be negated,
+    /// parsed as `T`.
+    pub fn as_float<T: FromStr>(&self) -> syn::Result<T>
+    where
+        T::Err: Display,
+    {
+        let (negative, lit) = match self {
+            DefineValue::Lit(lit) => (false, lit),
+            DefineValue::Expr(Expr::Unary(ExprUnary {
+                op: UnOp::Neg(_),
+                expr,
+                ..
+            })) => match expr.as_ref() {
+                Expr::Lit(ExprLit {
+                    lit, ..
+                }) => (true, lit),
+                _ => return Err(self.error("a float literal")),
+            },
+            _ => return Err(self.error("a float literal")),
+        };
+        let digits = match lit {
+            Lit::Float(lit) => lit.base10_digits(),
+            Lit::Int(lit) => lit.base10_digits(),
+            _ => return Err(self.error("a float literal")),
+        };
+        let sign = if negative { "-" } else { "" };
+        format!("{}{}", sign, digits)
+            .parse()
+            .map_err(|e| syn::Error::new_spanned(self, e))
+    }
+
+    pub fn as_path(&self) -> syn::Result<Path> {
+        match self {
+            DefineValue::Path(path) => Ok(path.clone()),
+            DefineValue::Expr(Expr::Path(ExprPath {
+                qself: None,
+                path,
+                ..
+            })) => Ok(path.clone()),
+            DefineValue::Type(Type::Path(TypePath {
+                qself: None,
+                path,
+            })) => Ok(path.clone()),
+            _ => Err(self.error("a path")),
+        }
+    }
+
+    pub fn as_ident(&self) -> syn::Result<Ident> {
+        let path = self.as_path().map_err(|_| self.error("an ident"))?;
+        match path.get_ident() {
+            Some(ident) => Ok(ident.clone()),
+            None => Err(self.error("an ident")),
+        }
+    }
+
+    pub fn as_expr(&self) -> syn::Result<Expr> {
+        match self {
+            DefineValue::Expr(expr) => Ok(expr.clone()),
+            DefineValue::Lit(lit) => Ok(Expr::Lit(ExprLit {
+                attrs: Vec::new(),
+                lit:   lit.clone(),
+            })),
+            DefineValue::Path(path) => Ok(Expr::Path(ExprPath {
+                attrs: Vec::new(),
+                qself: None,
+                path:  path.clone(),
+            })),
+            DefineValue::Block(block) => Ok(Expr::Block(ExprBlock {
+                attrs: Vec::new(),
+                label: None,
+                block: block.clone(),
+            })),
+            DefineValue::List(bracket, vals) => {
+                let mut elems = Punctuated::new();
+                for val in vals {
+                    elems.push(val.as_expr()?);
+                }
+                Ok(Expr::Array(ExprArray {
+                    attrs: Vec::new(),
+                    bracket_token: *bracket,
+                    elems,
+                }))
+            },
+            _ => syn::parse2(self.to_token_stream()).map_err(|_| self.error("an expression")),
+        }
+    }
+
+    pub fn as_type(&self) -> syn::Result<Type> {
+        match self {
+            DefineValue::Type(ty) => Ok(ty.clone()),
+            DefineValue::Path(path) => Ok(Type::Path(TypePath {
+                qself: None,
+                path:  path.clone(),
+            })),
+            _ => syn::parse2(self.to_token_stream()).map_err(|_| self.error("a type")),
+        }
+    }
+
+    pub fn as_block(&self) -> syn::Result<&Block> {
+        match self {
+            DefineValue::Block(block) => Ok(block),
+            _ => Err(self.error("a block")),
+        }
+    }
+
+    pub fn as_defines(&self) -> syn::Result<&Defines<S>> {
+        match self {
+            DefineValue::Defines(_, defines) => Ok(defines),
+            _ => Err(self.error("a block of defines")),
+        }
+    }
+
+    pub fn as_list(&self) -> syn::Result<&Punctuated<DefineValue<S>, token::Comma>> {
+        match self {
+            DefineValue::List(_, vals) => Ok(vals),
+            _ => Err(self.error("a list")),
+        }
+    }
+
+    /// See `Defines::deny_unknown`, `path` being the dotted path of this value.
+    fn collect_unknown(&self, path: &str, errors: &mut Option<syn::Error>) {
+        match self {
+            DefineValue::Defines(_, defines) => defines.collect_unknown(&format!("{}.", path), errors),
+            DefineValue::List(_, vals) => {
+                for (idx, val) in vals.iter().enumerate() {
+                    val.collect_unknown(&format!("{}.{}", path, idx), errors);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Sets the key policy of the blocks of defines in this value, including
+    /// the ones in lists, see `Defines::with_key_policy`.
+    fn set_key_policy(&mut self, key_policy: KeyPolicy) -> syn::Result<()> {
+        match self {
+            DefineValue::Defines(_, defines) => {
+                *defines = defines.clone().with_key_policy(key_policy)?;
+                Ok(())
+            },
+            DefineValue::List(_, vals) => {
+                let mut errors: Option<syn::Error> = None;
+                for val in vals.iter_mut() {
+                    if let Err(e) = val.set_key_policy(key_policy) {
+                        push_error(&mut errors, e);
+                    }
+                }
+                match errors {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                }
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S: DefineSeparators> Display for DefineValue<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_token_stream().to_string())
+    }
+}
+
+impl<S: DefineSeparators> ToTokens for DefineValue<S> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            DefineValue::Lit(v) => v.to_tokens(tokens),
+            DefineValue::Path(v) => v.to_tokens(tokens),
+            DefineValue::Expr(v) => v.to_tokens(tokens),
+            DefineValue::Type(v) => v.to_tokens(tokens),
+            DefineValue::Block(v) => v.to_tokens(tokens),
+            DefineValue::Defines(brace, v) => {
+                brace.surround(tokens, |inner| {
+                    v.to_tokens(inner);
+                });
+            },
+            DefineValue::List(bracket, v) => {
+                bracket.surround(tokens, |inner| {
+                    v.to_tokens(inner);
+                });
+            },
+            DefineValue::Verbatim(v) => v.to_tokens(tokens),
+        }
+    }
+}
+
+impl<S: DefineSeparators> Parse for DefineValue<S> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let end = input.fork();
+        skip_until_peek(&end, peek_delim::<token::Comma>, &mut Angles::default())?;
+        if end.cursor() == input.cursor() {
+            return Err(input.error("Expected a value"));
+        }
+        Self::classify_up_to(input, end.cursor())
+    }
+}
+
+/// Whether the content of braces is a block of defines, i.e it is empty or
+/// starts with a key and one of the separators of `S`.
+fn is_defines<S: DefineSeparators>(content: ParseStream) -> bool {
+    let fork = content.fork();
+    fork.is_empty()
+        || (fork.parse::<Ident>().is_ok()
+            && SeparatorKind::peek(&fork).is_some_and(|kind| S::ALLOWED.contains(&kind)))
+}
+
+/// Parses a `T` from the input when it ends right at `end` and is accepted by
+/// `accept`, leaving the input as it was otherwise.
+fn parse_exact<T: Parse>(input: ParseStream, end: Cursor, accept: impl FnOnce(&T) -> bool) -> Option<T> {
+    let fork = input.fork();
+    let val: T = fork.parse().ok()?;
+    if fork.cursor() != end || !accept(&val) {
+        return None;
+    }
+    input.advance_to(&fork);
+    Some(val)
+}
+
+/// Conversion from a `DefineValue`, for the typed lookups of `Defines` (e.g
+/// `Defines::get_as` and `Defines::get_or`).
+pub trait FromDefineValue<S: DefineSeparators = ColonSeparated>: Sized {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self>;
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for DefineValue<S> {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for String {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_str()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for bool {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_bool()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Lit {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_lit().cloned()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for LitStr {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_lit_str().cloned()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Path {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_path()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Ident {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_ident()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Expr {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_expr()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Type {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_type()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Block {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_block().cloned()
+    }
+}
+
+impl<S: DefineSeparators> FromDefineValue<S> for Defines<S> {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_defines().cloned()
+    }
+}
+
+impl<S: DefineSeparators, T: FromDefineValue<S>> FromDefineValue<S> for Vec<T> {
+    fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+        value.as_list()?.iter().map(T::from_define_value).collect()
+    }
+}
+
+macro_rules! impl_from_define_value {
+    ($method:ident, $($ty:ty),*) => {
+        $(
+            impl<S: DefineSeparators> FromDefineValue<S> for $ty {
+                fn from_define_value(value: &DefineValue<S>) -> syn::Result<Self> {
+                    value.$method()
+                }
+            }
+        )*
+    };
+}
+
+impl_from_define_value!(as_int, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+impl_from_define_value!(as_float, f32, f64);
+
+#[cfg(test)]
+mod tests {
+    use quote::{quote, ToTokens};
+
+    use super::{
+        to_snake_case,
+        AnySeparated,
+        ColonSeparated,
+        DefineSeparators,
+        DefineValue,
+        Defines,
+        EqSeparated,
+        FatArrowSeparated,
+        KeyPolicy,
+        SeparatorKind,
+    };
+
+    fn kinds(defines: &Defines) -> Vec<&'static str> {
+        defines.vals.iter().map(|v| v.value.describe()).collect()
+    }
+
+    #[test]
+    fn values() {
+        let defines: Defines = syn::parse_str(
+            r#"a: { b: 1, c: 2 }, b: {}, c: { let b = 1; b }, d: [1, 2, 3], e: "str",
+            f: some::path, g: 1 + foo(2), h: Vec<u8>, i: Foo(b: u8) -> Bar, j: Vec::<u8>::new"#,
+        )
+        .unwrap();
+        assert_eq!(kinds(&defines), [
+            "a block of defines",
+            "a block of defines",
+            "a block",
+            "a list",
+            "a string literal",
+            "a path",
+            "an expression",
+            "a type",
+            "an unrecognized value",
+            "an expression",
+        ]);
+        let again: Defines = syn::parse2(defines.to_token_stream()).unwrap();
+        assert_eq!(kinds(&again), kinds(&defines));
+
+        // Values end at the first comma that isn't nested, and are classified
+        // in place
+        let defines: Defines = syn::parse_str(
+            "a: HashMap<K, V>, b: |x, y| x + y, c: { d: [1, 2], e: Foo<u8, u16> }, f: 1 2",
+        )
+        .unwrap();
+        assert_eq!(kinds(&defines), [
+            "a type",
+            "an expression",
+            "a block of defines",
+            "an unrecognized value",
+        ]);
+        assert_eq!(defines.vals[3].value.to_token_stream().to_string(), "1 2");
+        let value = DefineValue::<ColonSeparated>::classify(quote!({ a: 1, b: [2, 3] })).unwrap();
+        assert_eq!(value.describe(), "a block of defines");
+        assert!(DefineValue::<ColonSeparated>::classify(quote!({ a: 1, b: })).is_err());
+    }
+
+    #[test]
+    fn accessors() {
+        let defines: Defines =
+            syn::parse_str(r#"a: "str", b: true, c: -42, d: 2.5, e: foo, f: [1, 2], g: Vec<u8>"#).unwrap();
+        let values: Vec<&DefineValue> = defines.vals.iter().map(|v| &v.value).collect();
+        assert_eq!(values[0].as_str().unwrap(), "str");
+        assert!(values[1].as_bool().unwrap());
+        assert_eq!(values[2].as_int::<i64>().unwrap(), -42);
+        assert_eq!(values[2].as_float::<f64>().unwrap(), -42.0);
+        assert_eq!(values[3].as_float::<f64>().unwrap(), 2.5);
+        assert_eq!(values[4].as_ident().unwrap(), "foo");
+        assert!(values[4].as_type().is_ok());
+        assert_eq!(values[5].as_list().unwrap().len(), 2);
+        assert!(values[5].as_expr().is_ok());
+        assert!(values[6].as_path().is_ok());
+
+        let err = values[4].as_int::<u8>().err().unwrap();
+        assert_eq!(err.to_string(), "Expected an integer literal, but found a path");
+        assert!(values[2].as_int::<u8>().is_err());
+    }
+
+    #[test]
+    fn lookups() {
+        let defines: Defines =
+            syn::parse_str(r#"label: "Save", enabled: true, width: 4, colr: Red"#).unwrap();
+        assert_eq!(defines.get_str("label").unwrap().unwrap(), "Save");
+        assert_eq!(defines.get_bool("enabled").unwrap(), Some(true));
+        assert_eq!(defines.get_or("width", 1u32).unwrap(), 4);
+        assert_eq!(defines.get_or("height", 1u32).unwrap(), 1);
+        assert_eq!(defines.require_as::<u32>("width").unwrap(), 4);
+        assert!(defines.get_bool("label").is_err());
+
+        let err = defines.require("color").err().unwrap();
+        assert_eq!(err.to_string(), "Missing required key `color`");
+        assert_eq!(err.span().start(), defines.set.span().start());
+    }
+
+    #[test]
+    fn key_policies() {
+        assert_eq!(to_snake_case("backgroundColor"), "background_color");
+        assert_eq!(to_snake_case("BackgroundColor"), "background_color");
+        assert_eq!(to_snake_case("HTTPServer"), "http_server");
+        assert_eq!(to_snake_case("background_color"), "background_color");
+
+        let defines: Defines = syn::parse_str("Width: 1, width: 2, backgroundColor: 3").unwrap();
+        let err = defines.clone().with_key_policy(KeyPolicy::CaseInsensitive).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Key `width` collides with `Width` (case-insensitive key matching)"
+        );
+        assert!(defines.get("WIDTH").is_err());
+
+        let exact = defines.with_key_policy(KeyPolicy::Exact).unwrap();
+        assert_eq!(exact.get_or("width", 0u8).unwrap(), 2);
+        assert!(exact.get("background_color").unwrap().is_none());
+        assert!(exact.with_key_policy(KeyPolicy::Normalized).is_err());
+
+        let defines: Defines = syn::parse_str("backgroundColor: 3").unwrap();
+        let normalized = defines.with_key_policy(KeyPolicy::Normalized).unwrap();
+        assert_eq!(normalized.get_or("background_color", 0u8).unwrap(), 3);
+    }
+
+    #[test]
+    fn take() {
+        let mut defines: Defines = syn::parse_str("Width: 1, width: 2, height: 3, extra: 4").unwrap();
+        assert!(defines.take("WIDTH").is_err());
+        assert_eq!(defines.len(), 4);
+
+        let height = defines.take("Height").unwrap().unwrap();
+        assert_eq!(height.ident, "height");
+        assert_eq!(defines.len(), 3);
+        assert!(defines.take("height").unwrap().is_none());
+
+        let mut defines: Defines = syn::parse_str("a: 1, b: 2").unwrap();
+        defines.take("a").unwrap();
+        let err = defines.deny_unknown().err().unwrap();
+        assert_eq!(err.to_string(), "Unknown key `b`");
+    }
+
+    #[test]
+    fn paths() {
+        let defines: Defines = syn::parse_str(
+            r##"theme: { colors: { primary: "#fff", accent: Red }, spacing: 4 },
+            items: [{ a: 1 }, { a: 2 }],
+            theme: { colors: { Extra: 1 }, fonts: ["Inter"] }"##,
+        )
+        .unwrap();
+        let defines = defines.merge_sections().unwrap();
+        assert_eq!(defines.len(), 2);
+        assert_eq!(defines.get_path_as::<String>("theme.colors.primary").unwrap().unwrap(), "#fff");
+        assert_eq!(defines.get_path_as::<u8>("items.1.a").unwrap().unwrap(), 2);
+        assert_eq!(defines.get_path_as::<String>("theme.fonts.0").unwrap().unwrap(), "Inter");
+        assert_eq!(defines.get_path_as::<u8>("theme.colors.extra").unwrap().unwrap(), 1);
+
+        let err = defines.require_path("theme.colors.primry").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Missing key `primry` in `theme.colors`, did you mean `primary`?"
+        );
+        let err = defines.require_path("items.5").err().unwrap();
+        assert_eq!(err.to_string(), "No item `5` in `items`, which is a list of 2 items");
+        assert!(defines.require_path("theme.spacing.x").is_err());
+
+        let exact = defines.with_key_policy(KeyPolicy::Exact).unwrap();
+        assert!(exact.get_path("theme.colors.extra").is_none());
+
+        let conflicting: Defines = syn::parse_str("a: 1, a: { b: 2 }").unwrap();
+        assert!(conflicting.merge_sections().is_err());
+    }
+
+    #[test]
+    fn deny_unknown() {
+        let defines: Defines = syn::parse_str(
+            "theme: { colors: { primary: White, extra: 1 }, spacing: 4 }, \
+             items: [{ a: 1 }, { a: 2, b: 3 }], other: { c: 1 }",
+        )
+        .unwrap();
+        defines.get_path("theme.colors.primary").unwrap();
+        defines.get_path("items.1.a").unwrap();
+        let errors: Vec<String> =
+            defines.deny_unknown().err().unwrap().into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, [
+            "Unknown key `theme.colors.extra`",
+            "Unknown key `theme.spacing`",
+            "Unknown key `items.0.a`",
+            "Unknown key `items.1.b`",
+            "Unknown key `other`",
+        ]);
+    }
+
+    #[test]
+    fn separators() {
+        fn error<S: DefineSeparators>(input: &str) -> String {
+            syn::parse_str::<Defines<S>>(input).err().unwrap().to_string()
+        }
+
+        let defines: Defines<EqSeparated> =
+            syn::parse_str(r#"a = 1, b = { c = 2 }, s = "x""#).unwrap();
+        assert_eq!(
+            defines.to_token_stream().to_string(),
+            r#"a = 1 , b = { c = 2 } , s = "x""#
+        );
+        assert_eq!(defines.get_path_as::<u8>("b.c").unwrap().unwrap(), 2);
+        let defines: Defines<FatArrowSeparated> = syn::parse_str("x => { y => 1 }").unwrap();
+        assert!(defines.get_path("x.y").is_some());
+
+        let defines: Defines<AnySeparated> =
+            syn::parse_str("a => 1, b := 2, c = 3, d: x == y").unwrap();
+        assert_eq!(
+            defines.to_token_stream().to_string(),
+            "a => 1 , b := 2 , c = 3 , d : x == y"
+        );
+        assert!(matches!(defines.vals[1].separator.kind(), SeparatorKind::ColonEq));
+        assert_eq!(defines.vals[3].value.describe(), "an expression");
+
+        assert_eq!(
+            error::<ColonSeparated>("a = 1"),
+            "Expected `:` to separate `a` and its value, but found `=`"
+        );
+        assert_eq!(
+            error::<ColonSeparated>("a := 1"),
+            "Expected `:` to separate `a` and its value, but found `:=`"
+        );
+        assert_eq!(
+            error::<EqSeparated>("a => 1"),
+            "Expected `=` to separate `a` and its value, but found `=>`"
+        );
+        assert_eq!(
+            error::<AnySeparated>("a 1"),
+            "Expected one of `:`, `=`, `=>`, `:=` to separate `a` and its value"
+        );
+    }
+}
+
@@ -1,17 +1,107 @@
 use std::fmt::{Debug, Display, Formatter};
 
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
 use syn::__private::TokenStream2;
+use syn::buffer::Cursor;
 use syn::Error;
-use syn::parse::{Parse, ParseStream, Peek};
+use syn::parse::discouraged::Speculative;
+use syn::parse::{Parse, ParseStream, Parser, Peek};
 use syn::token::Token;
 
 use debug_helpers::debug_file;
 
-use crate::{discard_next_token, token_name};
+use crate::token_name;
 
-pub struct ParseFirst<P: Parse + Display>(P);
+/// How deep `ParseFirst` and `PeekFirst` search the token groups of a stream
+/// (e.g `(..)` or `{..}`), depth first, when parsed with `parse_descending`.
+/// The default descends into every group, at any depth.
+#[derive(Clone, Debug, Default)]
+pub struct Descend {
+    /// The delimiters of the groups to descend into, any if empty.
+    pub delimiters: Vec<Delimiter>,
+    /// How many groups deep to descend, unlimited if `None`.
+    pub max_depth:  Option<usize>,
+}
+
+impl Descend {
+    pub fn with_delimiters(mut self, delimiters: &[Delimiter]) -> Self {
+        self.delimiters = delimiters.to_vec();
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    fn enters(&self, delimiter: Delimiter, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth < max_depth)
+            && (self.delimiters.is_empty() || self.delimiters.contains(&delimiter))
+    }
+}
+
+/// A group descended into on the way to a match, as the index of the group
+/// among the token trees of its parent stream.
+#[derive(Clone, Debug)]
+pub struct GroupStep {
+    pub index:     usize,
+    pub delimiter: Delimiter,
+    pub span:      Span,
+}
+
+/// The first `P` in `input`, searching the groups in it as `descend` allows,
+/// along with the groups it is nested in. Every token walked past is consumed,
+/// but not the match itself nor the tokens after it.
+fn find_first<P: Parse>(
+    input: ParseStream,
+    descend: Option<&Descend>,
+    depth: usize,
+) -> syn::Result<Option<(P, Vec<GroupStep>)>> {
+    let mut index = 0;
+    while !input.is_empty() {
+        if let Ok(p) = input.fork().parse::<P>() {
+            return Ok(Some((p, Vec::new())));
+        }
+        let next: TokenTree = input.parse()?;
+        if let (TokenTree::Group(group), Some(descend)) = (&next, descend) {
+            if descend.enters(group.delimiter(), depth) {
+                let search_group = |inner: ParseStream| {
+                    let result = find_first::<P>(inner, Some(descend), depth + 1)?;
+                    let _: TokenStream2 = inner.parse()?;
+                    Ok(result)
+                };
+                if let Some((p, mut path)) = search_group.parse2(group.stream())? {
+                    path.insert(
+                        0,
+                        GroupStep {
+                            index,
+                            delimiter: group.delimiter(),
+                            span: group.span(),
+                        },
+                    );
+                    return Ok(Some((p, path)));
+                }
+            }
+        }
+        index += 1;
+    }
+    Ok(None)
+}
+
+pub struct ParseFirst<P: Parse + Display>(P, Vec<GroupStep>);
+
+impl<P: Parse + Display> ParseFirst<P> {
+    pub fn get(&self) -> &P {
+        &self.0
+    }
+
+    /// The groups the match was found in, outermost first, which is empty
+    /// for a match at the top level.
+    pub fn path(&self) -> &[GroupStep] {
+        &self.1
+    }
+}
 
 impl<P: Parse + Display> Display for ParseFirst<P> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -26,8 +116,17 @@ impl<P: Parse + Display + quote::ToTokens> ToTokens for ParseFirst<P> {
     }
 }
 
-impl<P: Parse + Display + Token> Parse for ParseFirst<P> {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
+impl<P: Parse + Display + Token> ParseFirst<P> {
+    /// Parses the first `P` like `parse`, but also searches the groups of the
+    /// input as `descend` allows, e.g
+    /// ```text
+    /// let first = ParseFirst::<Lifetime>::parse_descending(input, &Descend::default().with_max_depth(2))?;
+    /// ```
+    pub fn parse_descending(input: ParseStream, descend: &Descend) -> syn::Result<Self> {
+        Self::new(input, Some(descend))
+    }
+
+    fn new(input: ParseStream, descend: Option<&Descend>) -> syn::Result<Self> {
         debug_file!(
             input,
             format!(
@@ -37,25 +136,21 @@ impl<P: Parse + Display + Token> Parse for ParseFirst<P> {
             )
         );
         let original_input = input.fork();
-        while !input.is_empty() {
-            let result: Option<P> = input.parse::<P>().ok();
-            if let Some(p) = result {
-                debug_file!(
-                    !"Finished parsing first of type {}, it was this: {}",
-                    token_name!(ty P),
-                    token_name!(ident p)
-                );
-                let _: TokenStream2 = input.parse()?;
-                return Ok(Self(p));
-            }
-            discard_next_token!(input);
+        if let Some((p, path)) = find_first::<P>(input, descend, 0)? {
+            debug_file!(
+                !"Finished parsing first of type {}, it was this: {}",
+                token_name!(ty P),
+                token_name!(ident p)
+            );
+            let _: TokenStream2 = input.parse()?;
+            return Ok(Self(p, path));
         }
         debug_file!(
             !"COULD NOT PARSE first of type {}, what was left was this: {}",
             token_name!(ty P),
             input
         );
-        return Err(Error::new(
+        Err(Error::new(
             input.span(),
             format!(
                 "Stream `{}` contained no elem of type {}",
@@ -63,11 +158,29 @@ impl<P: Parse + Display + Token> Parse for ParseFirst<P> {
                 P::display()
             )
             .as_str(),
-        ));
+        ))
+    }
+}
+
+impl<P: Parse + Display + Token> Parse for ParseFirst<P> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        Self::new(input, None)
     }
 }
 
-pub struct PeekFirst<P: Parse + Default>(P);
+pub struct PeekFirst<P: Parse + Default>(P, Vec<GroupStep>);
+
+impl<P: Parse + Default> PeekFirst<P> {
+    pub fn get(&self) -> &P {
+        &self.0
+    }
+
+    /// The groups the match was found in, outermost first, which is empty
+    /// for a match at the top level.
+    pub fn path(&self) -> &[GroupStep] {
+        &self.1
+    }
+}
 
 impl<P: Parse + Default + Token> Display for PeekFirst<P> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -75,24 +188,366 @@ impl<P: Parse + Default + Token> Display for PeekFirst<P> {
     }
 }
 
+impl<P: Parse + Default + Token> PeekFirst<P> {
+    /// Peeks the first `P` like `parse`, but also searches the groups of the
+    /// input as `descend` allows.
+    pub fn parse_descending(input: ParseStream, descend: &Descend) -> syn::Result<Self> {
+        Self::new(input, Some(descend))
+    }
+
+    fn new(input: ParseStream, descend: Option<&Descend>) -> syn::Result<Self> {
+        let fork = input.fork();
+        match find_first::<P>(&fork, descend, 0)? {
+            Some((p, path)) => Ok(Self(p, path)),
+            None => Err(Error::new(
+                input.span(),
+                format!(
+                    "Stream `{}` contained no elem of type {}",
+                    input,
+                    P::display()
+                )
+                .as_str(),
+            )),
+        }
+    }
+}
+
 impl<P: Parse + Default + Token> Parse for PeekFirst<P> {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let fork = input.fork();
-        while !fork.is_empty() {
-            let result: Option<P> = fork.parse::<P>().ok();
-            if let Some(p) = result {
-                return Ok(Self(p));
+        Self::new(input, None)
+    }
+}
+
+/// A match of a search in a stream (e.g of `ParseAll`), along with its span
+/// and the index of the top level token tree it starts at.
+pub struct Found<P: Parse> {
+    pub val:   P,
+    pub span:  Span,
+    pub index: usize,
+}
+
+impl<P: Parse + Display> Display for Found<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}", self.val))
+    }
+}
+
+impl<P: Parse + ToTokens> ToTokens for Found<P> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.val.to_tokens(tokens);
+    }
+}
+
+/// Walks the top level token trees of a stream, finding every `P` in it.
+struct Search<'a> {
+    input:   ParseStream<'a>,
+    index:   usize,
+    /// The tokens walked past without a match, since the last match.
+    skipped: TokenStream2,
+}
+
+impl<'a> Search<'a> {
+    fn new(input: ParseStream<'a>) -> Self {
+        Search {
+            input,
+            index: 0,
+            skipped: TokenStream2::new(),
+        }
+    }
+
+    /// The next `P`, continuing after it, or `None` once the stream is over.
+    fn next<P: Parse>(&mut self) -> syn::Result<Option<Found<P>>> {
+        while !self.input.is_empty() {
+            let fork = self.input.fork();
+            if let Ok(val) = fork.parse::<P>() {
+                let (span, len) = span_between(self.input.cursor(), fork.cursor());
+                let found = Found {
+                    val,
+                    span,
+                    index: self.index,
+                };
+                self.input.advance_to(&fork);
+                self.index += len;
+                // A `P` that parses from no tokens at all would match here forever
+                if len == 0 {
+                    let next: TokenTree = self.input.parse()?;
+                    self.skipped.extend(Some(next));
+                    self.index += 1;
+                }
+                return Ok(Some(found));
+            }
+            let next: TokenTree = self.input.parse()?;
+            self.skipped.extend(Some(next));
+            self.index += 1;
+        }
+        Ok(None)
+    }
+
+    fn take_skipped(&mut self) -> TokenStream2 {
+        std::mem::take(&mut self.skipped)
+    }
+}
+
+/// The joined span of the token trees from `start` up to `end`, and how many
+/// there are.
+fn span_between(start: Cursor, end: Cursor) -> (Span, usize) {
+    let mut span: Option<Span> = None;
+    let mut len = 0;
+    let mut cursor = start;
+    while cursor != end {
+        let Some((tt, next)) = cursor.token_tree() else {
+            break;
+        };
+        span = Some(match span {
+            Some(span) => span.join(tt.span()).unwrap_or(span),
+            None => tt.span(),
+        });
+        len += 1;
+        cursor = next;
+    }
+    (span.unwrap_or_else(|| start.span()), len)
+}
+
+fn no_elem_error<P>(input: ParseStream, original_input: &TokenStream2) -> Error {
+    Error::new(
+        input.span(),
+        format!(
+            "Stream `{}` contained no elem of type {}",
+            original_input,
+            token_name!(ty P)
+        ),
+    )
+}
+
+/// Every `P` in a stream, e.g every `#[prop]` marker with
+/// `ParseAll<PropMarker>`. Finding none is not an error.
+pub struct ParseAll<P: Parse>(pub Vec<Found<P>>);
+
+impl<P: Parse + Display> Display for ParseAll<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (idx, found) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_fmt(format_args!("{}", found))?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: Parse + ToTokens> ToTokens for ParseAll<P> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for found in &self.0 {
+            found.to_tokens(tokens);
+        }
+    }
+}
+
+impl<P: Parse> Parse for ParseAll<P> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut search = Search::new(input);
+        let mut result = Vec::new();
+        while let Some(found) = search.next()? {
+            result.push(found);
+        }
+        debug_file!(
+            !"Found {} elems of type {}",
+            result.len(),
+            token_name!(ty P)
+        );
+        Ok(Self(result))
+    }
+}
+
+/// The `N`th `P` in a stream, counting from 0 (so `ParseNth<P, 0>` is the same
+/// as `ParseFirst<P>`).
+pub struct ParseNth<P: Parse, const N: usize>(pub Found<P>);
+
+impl<P: Parse + Display, const N: usize> Display for ParseNth<P, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}", self.0))
+    }
+}
+
+impl<P: Parse + ToTokens, const N: usize> ToTokens for ParseNth<P, N> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.0.to_tokens(tokens);
+    }
+}
+
+impl<P: Parse, const N: usize> Parse for ParseNth<P, N> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let original_input: TokenStream2 = input.fork().parse()?;
+        let mut search = Search::new(input);
+        let mut count = 0;
+        while let Some(found) = search.next::<P>()? {
+            if count == N {
+                let _: TokenStream2 = input.parse()?;
+                return Ok(Self(found));
             }
-            discard_next_token!(input);
+            count += 1;
         }
-        return Err(Error::new(
+        Err(Error::new(
             input.span(),
             format!(
-                "Stream `{}` contained no elem of type {}",
-                input,
-                P::display()
-            )
-            .as_str(),
-        ));
+                "Stream `{}` contained {} elems of type {}, but expected at least {}",
+                original_input,
+                count,
+                token_name!(ty P),
+                N + 1
+            ),
+        ))
+    }
+}
+
+/// The last `P` in a stream.
+pub struct ParseLast<P: Parse>(pub Found<P>);
+
+impl<P: Parse + Display> Display for ParseLast<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{}", self.0))
+    }
+}
+
+impl<P: Parse + ToTokens> ToTokens for ParseLast<P> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.0.to_tokens(tokens);
+    }
+}
+
+impl<P: Parse> Parse for ParseLast<P> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let original_input: TokenStream2 = input.fork().parse()?;
+        let mut search = Search::new(input);
+        let mut last = None;
+        while let Some(found) = search.next::<P>()? {
+            last = Some(found);
+        }
+        match last {
+            Some(found) => Ok(Self(found)),
+            None => Err(no_elem_error::<P>(input, &original_input)),
+        }
+    }
+}
+
+/// The first `P` in a stream, along with the tokens before and after it, e.g
+/// for `a + b => c` with `ParseAround<Token![=>]>`, `a + b` and `c`.
+pub struct ParseAround<P: Parse> {
+    pub before: TokenStream2,
+    pub found:  Found<P>,
+    pub after:  TokenStream2,
+}
+
+impl<P: Parse + Display> Display for ParseAround<P> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{} {} {}", self.before, self.found, self.after))
+    }
+}
+
+impl<P: Parse + ToTokens> ToTokens for ParseAround<P> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.before.to_tokens(tokens);
+        self.found.to_tokens(tokens);
+        self.after.to_tokens(tokens);
+    }
+}
+
+impl<P: Parse> Parse for ParseAround<P> {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let original_input: TokenStream2 = input.fork().parse()?;
+        let mut search = Search::new(input);
+        match search.next::<P>()? {
+            Some(found) => Ok(Self {
+                before: search.take_skipped(),
+                found,
+                after: input.parse()?,
+            }),
+            None => Err(no_elem_error::<P>(input, &original_input)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proc_macro2::Delimiter;
+    use syn::parse::{ParseStream, Parser};
+    use syn::{Ident, LitInt, Token};
+
+    use super::{Descend, ParseAll, ParseAround, ParseFirst, ParseLast, ParseNth, PeekFirst};
+    use crate::TokenStream2;
+
+    fn first_descending(descend: Descend, src: &str) -> syn::Result<(String, Vec<(usize, Delimiter)>)> {
+        let parser = |input: ParseStream| ParseFirst::<LitInt>::parse_descending(input, &descend);
+        let first = parser.parse_str(src)?;
+        let path = first.path().iter().map(|step| (step.index, step.delimiter)).collect();
+        Ok((first.get().to_string(), path))
+    }
+
+    #[test]
+    fn descending() {
+        let src = "a (b [1]) 2";
+        let first: ParseFirst<LitInt> = syn::parse_str(src).unwrap();
+        assert_eq!(first.get().to_string(), "2");
+        assert!(first.path().is_empty());
+        assert_eq!(
+            first_descending(Descend::default(), src).unwrap(),
+            ("1".to_string(), vec![(1, Delimiter::Parenthesis), (1, Delimiter::Bracket)])
+        );
+        assert_eq!(
+            first_descending(Descend::default().with_max_depth(1), src).unwrap(),
+            ("2".to_string(), vec![])
+        );
+        assert_eq!(
+            first_descending(Descend::default().with_delimiters(&[Delimiter::Brace]), src).unwrap(),
+            ("2".to_string(), vec![])
+        );
+        assert!(first_descending(Descend::default().with_max_depth(1), "a ([1])").is_err());
+
+        // Peeking leaves the input as it was
+        let parser = |input: ParseStream| {
+            let peeked = PeekFirst::<Token![=>]>::parse_descending(input, &Descend::default())?;
+            let rest: TokenStream2 = input.parse()?;
+            Ok((peeked.path().len(), rest.to_string()))
+        };
+        assert_eq!(parser.parse_str("a { b => c }").unwrap(), (1, "a { b => c }".to_string()));
+        assert!(parser.parse_str("a { b = c }").is_err());
+    }
+
+    #[test]
+    fn all() {
+        let all: ParseAll<Token![=>]> = syn::parse_str("a => b, c => d").unwrap();
+        // `=>` is two token trees, which count for the index of the next match
+        let indices: Vec<usize> = all.0.iter().map(|found| found.index).collect();
+        assert_eq!(indices, [1, 6]);
+
+        let all: ParseAll<LitInt> = syn::parse_str("a (1) b").unwrap();
+        assert!(all.0.is_empty());
+    }
+
+    #[test]
+    fn nth_and_last() {
+        let nth: ParseNth<LitInt, 1> = syn::parse_str("1 a 2 b 3").unwrap();
+        assert_eq!(nth.0.val.base10_digits(), "2");
+        assert_eq!(nth.0.index, 2);
+        let err = syn::parse_str::<ParseNth<LitInt, 4>>("1 a 2 b 3").err().unwrap();
+        assert!(err.to_string().contains("contained 3 elems"), "{}", err);
+
+        let last: ParseLast<LitInt> = syn::parse_str("1 a 2 b 3 c").unwrap();
+        assert_eq!(last.0.val.base10_digits(), "3");
+        assert_eq!(last.0.index, 4);
+        let err = syn::parse_str::<ParseLast<LitInt>>("a b").err().unwrap();
+        assert!(err.to_string().contains("contained no elem"), "{}", err);
+    }
+
+    #[test]
+    fn around() {
+        let around: ParseAround<Token![=>]> = syn::parse_str("a + b => c => d").unwrap();
+        assert_eq!(around.before.to_string(), "a + b");
+        assert_eq!(around.after.to_string(), "c => d");
+        assert_eq!(around.found.index, 3);
+
+        let around: ParseAround<Ident> = syn::parse_str("a").unwrap();
+        assert!(around.before.is_empty() && around.after.is_empty());
+        assert!(syn::parse_str::<ParseAround<LitInt>>("a b").is_err());
     }
 }
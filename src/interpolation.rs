@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+
+use debug_helpers::debug_file;
+use proc_macro2::{Delimiter, Group, Punct, Span, TokenTree};
+use quote::ToTokens;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+
+use crate::define::{DefineSeparators, DefineValue, Defines};
+use crate::parse_utils::push_error;
+use crate::punct_set::PunctSet;
+use crate::suggest::did_you_mean_suffix;
+use crate::TokenStream2;
+
+impl<S: DefineSeparators> Defines<S> {
+    /// Substitutes the `${key}` references in the values of this block with
+    /// the (resolved) values of the referenced keys, e.g:
+    /// ```text
+    /// base: 4, padding: ${base} * 2, theme: { gap: ${padding} }
+    /// ```
+    /// becomes `base: 4, padding: 4 * 2, theme: { gap: 4 * 2 }`.
+    ///
+    /// A reference may be a dotted path (e.g `${colors.primary}`), and is
+    /// looked up in the block of the value first, then in the blocks enclosing
+    /// it. The substituted tokens are grouped, so `${a} * 2` multiplies all of
+    /// `a`, and a value that is just a reference takes the referenced value
+    /// as is. Cyclic and undefined references are reported, all combined into
+    /// a single error.
+    pub fn resolve_interpolations(&self) -> syn::Result<Self> {
+        let mut interpolator = Interpolator {
+            root:     self,
+            resolved: HashMap::new(),
+            stack:    Vec::new(),
+            errors:   None,
+        };
+        let result = interpolator.resolve_block(self, &[]);
+        match (result, interpolator.errors) {
+            (_, Some(e)) => Err(e),
+            (Some(result), None) => {
+                debug_file!(result, "Resolved the interpolations of Defines, value below:");
+                Ok(result)
+            },
+            (None, None) => unreachable!("Expected an error for an unresolved interpolation"),
+        }
+    }
+}
+
+struct Interpolator<'a, S: DefineSeparators> {
+    root:     &'a Defines<S>,
+    /// The resolved values by path, where `None` is a value that failed to
+    /// resolve (and was already reported).
+    resolved: HashMap<String, Option<DefineValue<S>>>,
+    stack:    Vec<String>,
+    errors:   Option<syn::Error>,
+}
+
+impl<'a, S: DefineSeparators> Interpolator<'a, S> {
+    fn error(&mut self, e: syn::Error) {
+        push_error(&mut self.errors, e);
+    }
+
+    fn resolve_block(&mut self, defines: &Defines<S>, path: &[String]) -> Option<Defines<S>> {
+        if let Err(e) = defines.check_collisions() {
+            self.error(e);
+            return None;
+        }
+        let mut vals = Vec::with_capacity(defines.vals.len());
+        for define in &defines.vals {
+            let mut define_path = path.to_vec();
+            define_path.push(defines.key_of(define));
+            if let Some(value) = self.resolve_path(&define_path, define.ident.span()) {
+                let mut define = define.clone();
+                define.value = value;
+                vals.push(define);
+            }
+        }
+        if vals.len() < defines.vals.len() {
+            return None;
+        }
+        Some(Defines {
+            set:        PunctSet {
+                vals,
+                delims: defines.delims.clone(),
+                spans: defines.spans.clone(),
+            },
+            key_policy: defines.key_policy,
+        })
+    }
+
+    /// Resolves the value at `path`, which is referenced at `span`.
+    fn resolve_path(&mut self, path: &[String], span: Span) -> Option<DefineValue<S>> {
+        let key = path.join(".");
+        if let Some(resolved) = self.resolved.get(&key) {
+            return resolved.clone();
+        }
+        if let Some(start) = self.stack.iter().position(|k| *k == key) {
+            let chain = self.stack[start..]
+                .iter()
+                .chain(Some(&key))
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.error(syn::Error::new(span, format!("Cyclic references: {}", chain)));
+            return None;
+        }
+        let value = self.find(path)?.1;
+        self.stack.push(key.clone());
+        let result = self.resolve_value(value, path);
+        self.stack.pop();
+        self.resolved.insert(key, result.clone());
+        result
+    }
+
+    fn resolve_value(&mut self, value: &DefineValue<S>, path: &[String]) -> Option<DefineValue<S>> {
+        match value {
+            DefineValue::Defines(brace, defines) => {
+                let defines = self.resolve_block(defines, path)?;
+                Some(DefineValue::Defines(*brace, defines))
+            },
+            DefineValue::List(bracket, vals) => {
+                let mut resolved = Punctuated::new();
+                let mut failed = false;
+                for (idx, val) in vals.iter().enumerate() {
+                    let mut item_path = path.to_vec();
+                    item_path.push(idx.to_string());
+                    match self.resolve_path(&item_path, val.span()) {
+                        Some(val) => resolved.push(val),
+                        None => failed = true,
+                    }
+                }
+                if failed {
+                    return None;
+                }
+                Some(DefineValue::List(*bracket, resolved))
+            },
+            _ => {
+                let tokens = value.to_token_stream();
+                if !has_interpolation(&tokens) {
+                    return Some(value.clone());
+                }
+                let mut iter = tokens.clone().into_iter();
+                if let (Some(TokenTree::Punct(dollar)), Some(TokenTree::Group(group)), None) =
+                    (iter.next(), iter.next(), iter.next())
+                {
+                    if is_interpolation(&dollar, &group) {
+                        return self.reference(&dollar, &group, path);
+                    }
+                }
+                let tokens = self.substitute(tokens, path)?;
+                match DefineValue::classify(tokens) {
+                    Ok(value) => Some(value),
+                    Err(e) => {
+                        self.error(e);
+                        None
+                    },
+                }
+            },
+        }
+    }
+
+    /// Replaces every `${..}` in the tokens (including the ones nested in
+    /// groups) with the referenced value, in a `Delimiter::None` group.
+    fn substitute(&mut self, tokens: TokenStream2, path: &[String]) -> Option<TokenStream2> {
+        let mut result = TokenStream2::new();
+        let mut failed = false;
+        let mut iter = tokens.into_iter().peekable();
+        while let Some(tt) = iter.next() {
+            match tt {
+                TokenTree::Punct(dollar) if dollar.as_char() == '$' => {
+                    let group = match iter.peek() {
+                        Some(TokenTree::Group(group)) if is_interpolation(&dollar, group) => group.clone(),
+                        _ => {
+                            result.extend(Some(TokenTree::Punct(dollar)));
+                            continue;
+                        },
+                    };
+                    iter.next();
+                    match self.reference(&dollar, &group, path) {
+                        Some(value) => {
+                            let mut substituted = Group::new(Delimiter::None, value.to_token_stream());
+                            substituted.set_span(group.span());
+                            result.extend(Some(TokenTree::Group(substituted)));
+                        },
+                        None => failed = true,
+                    }
+                },
+                TokenTree::Group(group) => {
+                    match self.substitute(group.stream(), path) {
+                        Some(stream) => {
+                            let mut substituted = Group::new(group.delimiter(), stream);
+                            substituted.set_span(group.span());
+                            result.extend(Some(TokenTree::Group(substituted)));
+                        },
+                        None => failed = true,
+                    }
+                },
+                tt => result.extend(Some(tt)),
+            }
+        }
+        if failed {
+            return None;
+        }
+        Some(result)
+    }
+
+    /// Resolves the `${..}` reference made by the value at `path`, looking it
+    /// up in the block of the value first, then in the enclosing blocks.
+    fn reference(&mut self, dollar: &Punct, group: &Group, path: &[String]) -> Option<DefineValue<S>> {
+        let span = dollar.span().join(group.span()).unwrap_or(group.span());
+        let segments = match reference_segments(group) {
+            Ok(segments) => segments,
+            Err(e) => {
+                self.error(e);
+                return None;
+            },
+        };
+        for depth in (0..path.len()).rev() {
+            let mut candidate = path[..depth].to_vec();
+            candidate.extend(segments.iter().cloned());
+            if let Some((canonical, _)) = self.find(&candidate) {
+                return self.resolve_path(&canonical, span);
+            }
+        }
+        let name = segments.join(".");
+        let scope = self.find(&path[..path.len() - 1]).map(|(_, v)| v);
+        let keys: Vec<String> = match scope {
+            Some(DefineValue::Defines(_, defines)) => defines.vals.iter().map(|v| v.key()).collect(),
+            _ => self.root.vals.iter().map(|v| v.key()).collect(),
+        };
+        let suggestion = did_you_mean_suffix(&name, keys.iter().map(|k| k.as_str()));
+        self.error(syn::Error::new(
+            span,
+            format!("Undefined reference `${{{}}}`{}", name, suggestion),
+        ));
+        None
+    }
+
+    /// The value at `segments` (keys, or indexes of lists), along with its
+    /// path with the keys normalized, as used for `resolved`. The root block
+    /// itself is found at an empty path.
+    fn find(&self, segments: &[String]) -> Option<(Vec<String>, &'a DefineValue<S>)> {
+        let mut canonical = Vec::with_capacity(segments.len());
+        let mut value: Option<&'a DefineValue<S>> = None;
+        let mut block: Option<&'a Defines<S>> = Some(self.root);
+        for segment in segments {
+            if let Some(defines) = block {
+                let key = defines.key_policy.normalize(segment);
+                let define = defines.vals.iter().find(|v| defines.key_of(v) == key)?;
+                canonical.push(key);
+                value = Some(&define.value);
+            } else if let Some(DefineValue::List(_, vals)) = value {
+                let idx: usize = segment.parse().ok()?;
+                canonical.push(idx.to_string());
+                value = vals.iter().nth(idx);
+            } else {
+                return None;
+            }
+            block = match value {
+                Some(DefineValue::Defines(_, defines)) => Some(defines),
+                _ => None,
+            };
+        }
+        value.map(|value| (canonical, value))
+    }
+}
+
+fn is_interpolation(dollar: &Punct, group: &Group) -> bool {
+    dollar.as_char() == '$' && group.delimiter() == Delimiter::Brace
+}
+
+fn has_interpolation(tokens: &TokenStream2) -> bool {
+    let mut prev_dollar: Option<Punct> = None;
+    for tt in tokens.clone() {
+        if let TokenTree::Group(group) = &tt {
+            if prev_dollar.as_ref().is_some_and(|d| is_interpolation(d, group)) {
+                return true;
+            }
+            if has_interpolation(&group.stream()) {
+                return true;
+            }
+        }
+        prev_dollar = match tt {
+            TokenTree::Punct(p) if p.as_char() == '$' => Some(p),
+            _ => None,
+        };
+    }
+    false
+}
+
+/// The dotted path inside of `${..}`, e.g `colors.primary` or `fonts.0`.
+fn reference_segments(group: &Group) -> syn::Result<Vec<String>> {
+    let error = |span: Span| {
+        syn::Error::new(
+            span,
+            "Expected a key path, e.g `${base}` or `${colors.primary}`",
+        )
+    };
+    let mut segments = Vec::new();
+    let mut expect_segment = true;
+    for tt in group.stream() {
+        match (&tt, expect_segment) {
+            (TokenTree::Ident(ident), true) => {
+                let key = ident.to_string();
+                segments.push(key.strip_prefix("r#").unwrap_or(&key).to_string());
+                expect_segment = false;
+            },
+            // `a.0.1` lexes as `a`, `.` and the float `0.1`
+            (TokenTree::Literal(lit), true) => {
+                let text = lit.to_string();
+                if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                    return Err(error(lit.span()));
+                }
+                segments.extend(text.split('.').map(|s| s.to_string()));
+                expect_segment = false;
+            },
+            (TokenTree::Punct(p), false) if p.as_char() == '.' => expect_segment = true,
+            _ => return Err(error(tt.span())),
+        }
+    }
+    if expect_segment {
+        return Err(error(group.span()));
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::define::Defines;
+
+    #[test]
+    fn resolve() {
+        let defines: Defines = syn::parse_str(
+            r#"base: 4, padding: ${base} * 2, name: ${label}, label: "x",
+            theme: { gap: ${padding}, inner: { g: ${gap} + ${theme.gap} } },
+            items: [${base}, { a: ${base} }], f: foo(${items.0}), first: ${items.1.a}"#,
+        )
+        .unwrap();
+        let resolved = defines.resolve_interpolations().unwrap();
+        assert_eq!(resolved.get_str("name").unwrap().unwrap(), "x");
+        assert_eq!(resolved.get_path_as::<u8>("items.0").unwrap().unwrap(), 4);
+        assert_eq!(resolved.get_path_as::<u8>("first").unwrap().unwrap(), 4);
+        let gap = resolved.get_path("theme.inner.g").unwrap().to_string();
+        assert_eq!(gap.split_whitespace().collect::<String>(), "4*2+4*2");
+        let padding = resolved.get_path("padding").unwrap().as_expr().unwrap();
+        assert!(matches!(padding, syn::Expr::Binary(_)));
+    }
+
+    #[test]
+    fn errors() {
+        let defines: Defines = syn::parse_str(
+            "a: ${b} + 1, b: ${c}, c: ${a}, d: ${bse}, base: 1, e: ${}, x: { y: ${nope} }",
+        )
+        .unwrap();
+        let errors: Vec<String> = defines
+            .resolve_interpolations()
+            .err()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(errors, [
+            "Cyclic references: a -> b -> c -> a",
+            "Undefined reference `${bse}`, did you mean `base`?",
+            "Expected a key path, e.g `${base}` or `${colors.primary}`",
+            "Undefined reference `${nope}`",
+        ]);
+    }
+}
@@ -1,14 +1,22 @@
 pub use debug_helpers::debug_file as __pmmh_debug_file;
 
+pub mod constants;
 pub mod declaration;
 pub mod define;
 pub mod dsl_macros;
 pub mod find_first;
+pub mod interpolation;
+pub mod node;
 pub mod param;
 pub mod parse_utils;
 pub mod punct_set;
 pub mod quote_if;
+pub mod schema;
+pub mod string_enum;
 pub mod stringify;
+pub mod suggest;
+pub mod symbols;
+pub mod template;
 
 use lazy_static::lazy_static;
 
@@ -0,0 +1,536 @@
+use debug_helpers::debug_file;
+use derive_display::derive_display;
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use syn::__private::TokenStream2;
+use syn::parse::{Parse, ParseStream};
+use syn::{braced, token, Expr, Pat, Token};
+
+use crate::declaration::Declaration;
+use crate::param::Args;
+use crate::parse_utils::parse_until_peek;
+
+/// A declaration of a tree, which takes value arguments (see `Args`), e.g
+/// `Button("Ok", padding: 8)`, and has child nodes as its body.
+pub type NodeDeclaration = Declaration<Nodes, Args>;
+
+/// A sequence of child nodes, as found inside a declaration body. Children may
+/// be separated by `,` or `;`, and a child with a braced body needs no
+/// separator at all, e.g:
+/// ```text
+/// Text("Header")
+/// if show_button {
+///     Button(label: "Click")
+/// } else {
+///     Text("Nothing to click")
+/// }
+/// for item in items {
+///     Row(item) { Text(item.name) }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Nodes {
+    pub vals: Vec<Node>,
+}
+
+impl Nodes {
+    pub fn empty() -> Self {
+        Nodes {
+            vals: Vec::new(),
+        }
+    }
+
+    /// Generates the statements that build every child in order and push them
+    /// into the `children` collection, which is expected to be in scope and
+    /// mutable where the result is expanded.
+    pub fn as_widget_build_block(&self, children: &Ident) -> TokenStream2 {
+        let mut result = TokenStream2::new();
+        for val in &self.vals {
+            val.as_widget_build_block(children).to_tokens(&mut result);
+        }
+        result
+    }
+
+    /// Same as `as_widget_build_block`, but wrapped into a block expression that
+    /// evaluates to the collected `Vec` of children.
+    pub fn as_widget_build_vec(&self) -> TokenStream2 {
+        let children = Ident::new("__children", proc_macro2::Span::call_site());
+        let block = self.as_widget_build_block(&children);
+        quote! {
+            {
+                let mut #children = Vec::new();
+                #block
+                #children
+            }
+        }
+    }
+}
+
+#[derive_display]
+impl ToTokens for Nodes {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        for val in &self.vals {
+            val.to_tokens(tokens);
+            if let Node::Declaration(d) = val {
+                if d.body.is_none() {
+                    token::Comma::default().to_tokens(tokens);
+                }
+            }
+        }
+    }
+}
+
+impl Parse for Nodes {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        debug_file!(input, "Starting to parse Nodes, input below:");
+        let mut vals = Vec::new();
+        while !input.is_empty() {
+            vals.push(input.parse()?);
+            while input.peek(Token![,]) || input.peek(Token![;]) {
+                crate::discard_next_token!(input);
+            }
+        }
+        Ok(Self {
+            vals,
+        })
+    }
+}
+
+/// A single node of a declaration tree. Either a plain `Declaration`, or one of
+/// the Rust control-flow forms wrapping more nodes:
+/// ```text
+/// Button(label: "Ok")
+/// if cond { Button(label: "Ok") } else { Text("Not ok") }
+/// for item in items { Row(item) }
+/// match state { State::A => Text("a"), _ => { Text("other") } }
+/// ```
+#[derive(Clone)]
+pub enum Node {
+    Declaration(NodeDeclaration),
+    If(IfNode),
+    For(ForNode),
+    Match(MatchNode),
+}
+
+impl Node {
+    /// Generates the runtime code that builds this node into `children`. Plain
+    /// declarations are constructed with their args (see `Args::as_ctor_call`)
+    /// and pushed as-is (through `Into`), with the children of their body
+    /// built first and passed on through `with_children`, whereas control-flow
+    /// nodes are expanded into the corresponding Rust control flow around the
+    /// build blocks of their own children.
+    pub fn as_widget_build_block(&self, children: &Ident) -> TokenStream2 {
+        match self {
+            Node::Declaration(d) => {
+                let mut build = match &d.params {
+                    Some(args) => args.as_ctor_call(&d.ty),
+                    None => d.as_widget_build_block(),
+                };
+                if let Some((_, body)) = &d.body {
+                    let body = body.as_widget_build_vec();
+                    build = quote!(#build.with_children(#body));
+                }
+                quote! {
+                    #children.push(#build.into());
+                }
+            },
+            Node::If(n) => n.as_widget_build_block(children),
+            Node::For(n) => n.as_widget_build_block(children),
+            Node::Match(n) => n.as_widget_build_block(children),
+        }
+    }
+}
+
+#[derive_display]
+impl ToTokens for Node {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Node::Declaration(d) => d.to_tokens(tokens),
+            Node::If(n) => n.to_tokens(tokens),
+            Node::For(n) => n.to_tokens(tokens),
+            Node::Match(n) => n.to_tokens(tokens),
+        }
+    }
+}
+
+impl Parse for Node {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![if]) {
+            return Ok(Node::If(input.parse()?));
+        }
+        if input.peek(Token![for]) {
+            return Ok(Node::For(input.parse()?));
+        }
+        if input.peek(Token![match]) {
+            return Ok(Node::Match(input.parse()?));
+        }
+        Ok(Node::Declaration(parse_child_declaration(input)?))
+    }
+}
+
+/// Parses a single declaration of a tree. The declaration ends at the first
+/// top-level `,` or `;` (i.e not in generics, see `parse_until_peek`), at the
+/// start of a control-flow node, or right after its braced body. A keyword
+/// right after a `:` or a `+` belongs to the trails instead (e.g `for` in
+/// `Foo(a): for<'a> Fn(&'a str)`), so it doesn't start a node.
+fn parse_child_declaration(input: ParseStream) -> syn::Result<NodeDeclaration> {
+    let mut started = false;
+    let mut in_bound = false;
+    let (mut tokens, _) = parse_until_peek(input, |input| {
+        let is_end = input.peek(Token![,])
+            || input.peek(Token![;])
+            || input.peek(token::Brace)
+            || (started
+                && !in_bound
+                && (input.peek(Token![if]) || input.peek(Token![for]) || input.peek(Token![match])));
+        started = true;
+        in_bound = input.peek(Token![:]) || input.peek(Token![+]);
+        is_end
+    })?;
+    if tokens.is_empty() {
+        return Err(input.error("Expected a Declaration"));
+    }
+    if input.peek(token::Brace) {
+        tokens.extend(Some(input.parse::<TokenTree>()?));
+    }
+    let declaration: NodeDeclaration = syn::parse2(tokens)?;
+    debug_file!(declaration, "Parsed a child Declaration, value below:");
+    Ok(declaration)
+}
+
+/// e.g:
+/// ```text
+/// if cond { Foo(a) } else if other { Bar(b) } else { Baz }
+/// ```
+#[derive(Clone)]
+pub struct IfNode {
+    pub if_token:    Token![if],
+    pub cond:        Box<Expr>,
+    pub brace_token: token::Brace,
+    pub then_branch: Nodes,
+    pub else_branch: Option<(Token![else], Box<ElseBranch>)>,
+}
+
+/// Whatever follows the `else` of an `IfNode`.
+#[derive(Clone)]
+pub enum ElseBranch {
+    If(IfNode),
+    Nodes(token::Brace, Nodes),
+}
+
+impl IfNode {
+    pub fn as_widget_build_block(&self, children: &Ident) -> TokenStream2 {
+        let cond = &self.cond;
+        let then_branch = self.then_branch.as_widget_build_block(children);
+        let else_branch = match &self.else_branch {
+            Some((_, branch)) => match branch.as_ref() {
+                ElseBranch::If(n) => {
+                    let n = n.as_widget_build_block(children);
+                    quote!(else #n)
+                },
+                ElseBranch::Nodes(_, nodes) => {
+                    let nodes = nodes.as_widget_build_block(children);
+                    quote!(else { #nodes })
+                },
+            },
+            None => quote!(),
+        };
+        quote! {
+            if #cond {
+                #then_branch
+            } #else_branch
+        }
+    }
+}
+
+#[derive_display]
+impl ToTokens for IfNode {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.if_token.to_tokens(tokens);
+        self.cond.to_tokens(tokens);
+        self.brace_token.surround(tokens, |inner| {
+            self.then_branch.to_tokens(inner);
+        });
+        if let Some((else_token, branch)) = &self.else_branch {
+            else_token.to_tokens(tokens);
+            match branch.as_ref() {
+                ElseBranch::If(n) => n.to_tokens(tokens),
+                ElseBranch::Nodes(brace, nodes) => {
+                    brace.surround(tokens, |inner| {
+                        nodes.to_tokens(inner);
+                    });
+                },
+            }
+        }
+    }
+}
+
+impl Parse for IfNode {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let if_token: Token![if] = input.parse()?;
+        let cond = Box::new(Expr::parse_without_eager_brace(input)?);
+        let content;
+        let brace_token = braced!(content in input);
+        let then_branch: Nodes = content.parse()?;
+        let mut else_branch = None;
+        if input.peek(Token![else]) {
+            let else_token: Token![else] = input.parse()?;
+            let branch = if input.peek(Token![if]) {
+                ElseBranch::If(input.parse()?)
+            } else {
+                let content;
+                let brace = braced!(content in input);
+                ElseBranch::Nodes(brace, content.parse()?)
+            };
+            else_branch = Some((else_token, Box::new(branch)));
+        }
+        Ok(Self {
+            if_token,
+            cond,
+            brace_token,
+            then_branch,
+            else_branch,
+        })
+    }
+}
+
+/// e.g:
+/// ```text
+/// for (idx, item) in items.iter().enumerate() { Row(idx, item) }
+/// ```
+#[derive(Clone)]
+pub struct ForNode {
+    pub for_token:   Token![for],
+    pub pat:         Box<Pat>,
+    pub in_token:    Token![in],
+    pub expr:        Box<Expr>,
+    pub brace_token: token::Brace,
+    pub body:        Nodes,
+}
+
+impl ForNode {
+    pub fn as_widget_build_block(&self, children: &Ident) -> TokenStream2 {
+        let pat = &self.pat;
+        let expr = &self.expr;
+        let body = self.body.as_widget_build_block(children);
+        quote! {
+            for #pat in #expr {
+                #body
+            }
+        }
+    }
+}
+
+#[derive_display]
+impl ToTokens for ForNode {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.for_token.to_tokens(tokens);
+        self.pat.to_tokens(tokens);
+        self.in_token.to_tokens(tokens);
+        self.expr.to_tokens(tokens);
+        self.brace_token.surround(tokens, |inner| {
+            self.body.to_tokens(inner);
+        });
+    }
+}
+
+impl Parse for ForNode {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let for_token = input.parse()?;
+        let pat = Box::new(Pat::parse_multi_with_leading_vert(input)?);
+        let in_token = input.parse()?;
+        let expr = Box::new(Expr::parse_without_eager_brace(input)?);
+        let content;
+        let brace_token = braced!(content in input);
+        let body = content.parse()?;
+        Ok(Self {
+            for_token,
+            pat,
+            in_token,
+            expr,
+            brace_token,
+            body,
+        })
+    }
+}
+
+/// e.g:
+/// ```text
+/// match state {
+///     State::Loading => Spinner,
+///     State::Ready(data) if data.is_empty() => Text("Empty"),
+///     State::Ready(data) => { List(data) }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct MatchNode {
+    pub match_token: Token![match],
+    pub expr:        Box<Expr>,
+    pub brace_token: token::Brace,
+    pub arms:        Vec<MatchNodeArm>,
+}
+
+/// A single arm of a `MatchNode`. An arm body without braces holds exactly one
+/// node.
+#[derive(Clone)]
+pub struct MatchNodeArm {
+    pub pat:             Pat,
+    pub guard:           Option<(Token![if], Box<Expr>)>,
+    pub fat_arrow_token: Token![=>],
+    pub brace_token:     Option<token::Brace>,
+    pub body:            Nodes,
+}
+
+impl MatchNode {
+    pub fn as_widget_build_block(&self, children: &Ident) -> TokenStream2 {
+        let expr = &self.expr;
+        let arms = self.arms.iter().map(|arm| {
+            let pat = &arm.pat;
+            let guard = arm.guard.as_ref().map(|(if_token, cond)| quote!(#if_token #cond));
+            let body = arm.body.as_widget_build_block(children);
+            quote! {
+                #pat #guard => {
+                    #body
+                }
+            }
+        });
+        quote! {
+            match #expr {
+                #(#arms)*
+            }
+        }
+    }
+}
+
+#[derive_display]
+impl ToTokens for MatchNode {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.match_token.to_tokens(tokens);
+        self.expr.to_tokens(tokens);
+        self.brace_token.surround(tokens, |inner| {
+            for arm in &self.arms {
+                arm.pat.to_tokens(inner);
+                if let Some((if_token, cond)) = &arm.guard {
+                    if_token.to_tokens(inner);
+                    cond.to_tokens(inner);
+                }
+                arm.fat_arrow_token.to_tokens(inner);
+                match &arm.brace_token {
+                    Some(brace) => {
+                        brace.surround(inner, |arm_inner| {
+                            arm.body.to_tokens(arm_inner);
+                        });
+                    },
+                    None => {
+                        arm.body.to_tokens(inner);
+                    },
+                }
+            }
+        });
+    }
+}
+
+impl Parse for MatchNode {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let match_token = input.parse()?;
+        let expr = Box::new(Expr::parse_without_eager_brace(input)?);
+        let content;
+        let brace_token = braced!(content in input);
+        let mut arms = Vec::new();
+        while !content.is_empty() {
+            let pat = Pat::parse_multi_with_leading_vert(&content)?;
+            let mut guard = None;
+            if content.peek(Token![if]) {
+                guard = Some((content.parse()?, Box::new(content.parse()?)));
+            }
+            let fat_arrow_token = content.parse()?;
+            let (brace_token, body) = if content.peek(token::Brace) {
+                let arm_content;
+                let brace = braced!(arm_content in content);
+                (Some(brace), arm_content.parse()?)
+            } else {
+                (None, Nodes {
+                    vals: vec![content.parse()?],
+                })
+            };
+            if content.peek(Token![,]) {
+                let _: Token![,] = content.parse()?;
+            }
+            arms.push(MatchNodeArm {
+                pat,
+                guard,
+                fat_arrow_token,
+                brace_token,
+                body,
+            });
+        }
+        Ok(Self {
+            match_token,
+            expr,
+            brace_token,
+            arms,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::{format_ident, quote, ToTokens};
+
+    use super::{Node, Nodes};
+
+    fn build(nodes: &str) -> String {
+        let nodes: Nodes = syn::parse_str(nodes).unwrap();
+        nodes.as_widget_build_block(&format_ident!("children")).to_string()
+    }
+
+    #[test]
+    fn control_flow() {
+        let nodes: Nodes = syn::parse_str(
+            "Text(\"Header\")
+            if show_button { Button(label: \"Click\") } else { Text(\"Nothing to click\") }
+            for item in items { Row(item) { Text(item.name) } }
+            match state { State::A => Text(\"a\"), _ => { Text(\"other\") } }",
+        )
+        .unwrap();
+        assert_eq!(nodes.vals.len(), 4);
+        assert!(matches!(nodes.vals[1], Node::If(_)));
+        assert!(matches!(nodes.vals[2], Node::For(_)));
+        assert!(matches!(nodes.vals[3], Node::Match(_)));
+        let again: Nodes = syn::parse2(nodes.to_token_stream()).unwrap();
+        assert_eq!(again.to_string(), nodes.to_string());
+    }
+
+    #[test]
+    fn build_blocks() {
+        let expected = quote! {
+            children.push(Text::new("x").into());
+            children.push(Btn::new().b(1).into());
+        };
+        assert_eq!(build("Text(\"x\"), Btn(b: 1)"), expected.to_string());
+        let expected = quote! {
+            for item in items {
+                children.push(Row::new(item).with_children({
+                    let mut __children = Vec::new();
+                    __children.push(Text::new(item.name).into());
+                    __children
+                }).into());
+            }
+        };
+        assert_eq!(build("for item in items { Row(item) { Text(item.name) } }"), expected.to_string());
+    }
+
+    #[test]
+    fn trails() {
+        let nodes: Nodes =
+            syn::parse_str("Foo(a): Into<HashMap<K, V>>, Bar(b): for<'a> Fn(&'a str); Text(\"x\")")
+                .unwrap();
+        assert_eq!(nodes.vals.len(), 3);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(syn::parse_str::<Nodes>("\"text\"").is_err());
+        assert!(syn::parse_str::<Nodes>("Btn(b: )").is_err());
+    }
+}
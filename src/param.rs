@@ -1,13 +1,14 @@
 use std::fmt::{Display, Formatter};
 
 use debug_helpers::debug_file;
+use derive_display::derive_display;
 use proc_macro2::{Ident, TokenStream, TokenTree};
 use quote::{quote, ToTokens};
 use syn::__private::TokenStream2;
 use syn::parse::{Parse, ParseStream, Parser};
 use syn::{parenthesized, token, Expr, Ident as IdentSyn, Token, Type};
 
-use crate::define::Define;
+use crate::define::{Define, DefineValue};
 use crate::find_first::ParseFirst;
 use crate::parse_utils::AnyParsable;
 use crate::punct_set::PunctSet;
@@ -15,6 +16,10 @@ use crate::{surround, EMPTY, EMPTY_STR};
 
 pub type Params = PunctSet<Param>;
 
+/// Value arguments, as opposed to the typed `Params`, e.g the params of
+/// `Button("Ok", padding: 8)`.
+pub type Args = PunctSet<Arg>;
+
 impl Params {
     pub fn as_regular_let_decls(&self) -> TokenStream2 {
         let mut result = TokenStream2::new();
@@ -77,8 +82,33 @@ impl Params {
     }
 }
 
+impl Args {
+    /// The args without a name, in order.
+    pub fn positional(&self) -> impl Iterator<Item = &Expr> {
+        self.vals.iter().filter(|v| v.name.is_none()).map(|v| &v.value)
+    }
+
+    /// The args with a name, in order.
+    pub fn named(&self) -> impl Iterator<Item = (&Ident, &Expr)> {
+        self.vals
+            .iter()
+            .filter_map(|v| v.name.as_ref().map(|(name, _)| (name, &v.value)))
+    }
+
+    /// Calls the constructor of `ty` with the positional args, and then a
+    /// setter for each named arg, e.g `Button::new("Ok").padding(8)` for
+    /// `Button("Ok", padding: 8)`.
+    pub fn as_ctor_call(&self, ty: &Ident) -> TokenStream2 {
+        let positional = self.positional();
+        let setters = self.named().map(|(name, value)| quote!(.#name(#value)));
+        quote! {
+            #ty::new(#(#positional),*) #(#setters)*
+        }
+    }
+}
+
 /// e.g:
-/// ```
+/// ```text
 /// pub mut foo: Bar(fish: Fash = default()) = Bar::new(xyz)
 /// mut a: u8 = 1
 /// pub b: String = "aye".to_string()
@@ -146,13 +176,13 @@ impl Param {
     pub fn as_define(&self) -> Define {
         let name = self.name.clone();
         let value = self.ty.clone();
-        Define {
-            ident: name.expect(
+        Define::new(
+            name.expect(
                 "Expected Param that can be converted to a Define, to have a \
             name identity (i.e `name: Type`, wherein the `name` is missing here",
             ),
-            value: value.into_token_stream(),
-        }
+            DefineValue::Type(value),
+        )
     }
 }
 
@@ -225,7 +255,7 @@ impl Parse for Param {
         let mutability: Option<Token![mut]> = input.parse().ok();
         let mut name: Option<Ident> = None;
         let mut type_prefix: Option<Token![:]> = None;
-        if input.peek2(Token![:]) && input.peek3(IdentSyn) {
+        if input.peek(IdentSyn) && input.peek2(Token![:]) && !input.peek2(Token![::]) {
             name = input.parse().ok();
             type_prefix = input.parse().ok();
         }
@@ -239,7 +269,10 @@ impl Parse for Param {
         let _ty: Option<Type> = input.parse().ok();
         if _ty.is_none() {
             let some: TokenTree = input.parse()?;
-            let msg = format!("Expected a Type for a Param, but got: `{}`", some);
+            let msg = format!(
+                "Expected a Type for a Param, but got: `{}` (see `Args` for params that are values)",
+                some
+            );
             debug_file!(!"\tERROR: {}", msg);
             return Err(input.error(msg));
         }
@@ -280,3 +313,41 @@ impl Parse for Param {
         Ok(result)
     }
 }
+
+/// A single value argument, optionally named, e.g:
+/// ```text
+/// "Save"
+/// padding: 8
+/// focus: save_btn
+/// ```
+#[derive(Clone)]
+pub struct Arg {
+    pub name:  Option<(Ident, Token![:])>,
+    pub value: Expr,
+}
+
+#[derive_display]
+impl ToTokens for Arg {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        if let Some((name, colon)) = &self.name {
+            name.to_tokens(tokens);
+            colon.to_tokens(tokens);
+        }
+        self.value.to_tokens(tokens);
+    }
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut name = None;
+        if input.peek(IdentSyn) && input.peek2(Token![:]) && !input.peek2(Token![::]) {
+            name = Some((input.parse()?, input.parse()?));
+        }
+        let value: Expr = input.parse()?;
+        debug_file!(!"\t<<< Found value `{}` for the arg", value.to_token_stream());
+        Ok(Self {
+            name,
+            value,
+        })
+    }
+}
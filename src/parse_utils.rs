@@ -1,13 +1,16 @@
-use std::any::{Any, TypeId};
+use std::cmp::Ordering;
 use std::fmt::Display;
 
-use proc_macro2::TokenTree;
+use proc_macro2::{Punct, Spacing, Span, TokenTree};
 use quote::ToTokens;
 use syn::__private::TokenStream2;
+use syn::buffer::Cursor;
+use syn::parse::discouraged::Speculative;
 use syn::parse::{Parse, ParseBuffer, ParseStream, Parser, Peek};
 use syn::token::Token;
 use syn::Token;
 
+use crate::punct_set::Delimiter;
 use crate::{__pmmh_debug_file, token_name};
 
 pub struct AnyParsable(TokenTree);
@@ -27,6 +30,16 @@ impl ToTokens for AnyParsable {
     }
 }
 
+/// Combines `error` into `errors`, so that every error found while parsing or
+/// checking can be reported at once.
+pub fn push_error(errors: &mut Option<syn::Error>, error: syn::Error) {
+    match errors {
+        Some(e) => e.combine(error),
+        None => *errors = Some(error),
+    }
+}
+
+#[deprecated(note = "Use `declaration::Declarations` to parse `#` separated documents")]
 pub fn find_end_of_widget_body(input: ParseStream) -> syn::Result<TokenStream2> {
     let mut output = TokenStream2::new();
     let mut braces_counter = 0;
@@ -140,31 +153,436 @@ pub fn comes_next_any_surrounder(input: &ParseBuffer, dir: SurrounderDir) -> boo
     }
 }
 
-pub fn parse_until<E: Token + Parse + Default + 'static>(
+/// Parses the tokens up to the first `end` that is not nested in generics
+/// (e.g the `,` in `HashMap<K, V>`) or in the params of a closure (e.g the
+/// `,` in `|a, b| a + b`), leaving `end` in the input.
+pub fn parse_until<E: Delimiter + 'static>(
     input: ParseStream,
     end: E,
 ) -> syn::Result<TokenStream2> {
-    let mut tokens = TokenStream2::new();
+    parse_until_spanned(input, end).map(|(tokens, _)| tokens)
+}
+
+/// Same as `parse_until`, along with the span of the parsed tokens, or the
+/// span of `end` when there are none.
+///
+/// The input is walked token by token, and only tried to be parsed as `end`
+/// where `E::peek` finds one.
+pub fn parse_until_spanned<E: Delimiter + 'static>(
+    input: ParseStream,
+    end: E,
+) -> syn::Result<(TokenStream2, Span)> {
+    parse_until_peek(input, peek_delim::<E>)
+}
+
+/// Same as `parse_until_spanned`, but ends at the first token that is not
+/// nested where `is_end` holds, e.g at any of several delimiters. `is_end` is
+/// called once for every such token, in order.
+pub fn parse_until_peek(
+    input: ParseStream,
+    is_end: impl FnMut(ParseStream) -> bool,
+) -> syn::Result<(TokenStream2, Span)> {
+    let start = input.cursor();
+    let span = skip_until_peek(input, is_end, &mut Angles::default())?;
+    Ok((tokens_between(start, input.cursor()), span))
+}
+
+/// Same as `parse_until_peek`, but only steps over the tokens, returning the
+/// span of the tokens stepped over. The lookahead for generics is kept in
+/// `angles`, so that it is done once for every `<` of the input, across the
+/// calls sharing it.
+pub(crate) fn skip_until_peek<'a>(
+    input: &ParseBuffer<'a>,
+    mut is_end: impl FnMut(ParseStream) -> bool,
+    angles: &mut Angles<'a>,
+) -> syn::Result<Span> {
+    let mut nesting = Nesting::new(angles);
+    let mut span: Option<Span> = None;
     while !input.is_empty() {
+        let cursor = input.cursor();
+        if nesting.is_top_level() && !nesting.joins_next(cursor) && is_end(input) {
+            break;
+        }
+        let next: TokenTree = input.parse()?;
+        __pmmh_debug_file!(!"\t\t << Found TokenTree `{}`", next);
+        nesting.step(cursor);
+        span = Some(match span {
+            Some(start) => start.join(next.span()).unwrap_or(start),
+            None => next.span(),
+        });
+    }
+    Ok(span.unwrap_or_else(|| input.span()))
+}
+
+/// Parses a `T` from the tokens of the input up to `end`, which is a later
+/// cursor of it (e.g found with `skip_until_peek` on a fork), leaving the input
+/// at `end`. While `live` is set, `T` is parsed from the input itself, which
+/// holds when `T` stops right at `end`. Otherwise `T` is parsed from a copy of
+/// the tokens, so that it can't go on past `end` and errors on the tokens it
+/// leaves before `end`, and `live` is cleared when `T` went on past `end` (as
+/// `TokenStream2` does), since it would for the next elements too.
+pub(crate) fn parse_up_to<T: Parse>(input: ParseStream, end: Cursor, live: &mut bool) -> syn::Result<T> {
+    if *live {
         let fork = input.fork();
-        let tt: Option<TokenTree> = fork.parse().ok();
-        __pmmh_debug_file!(
-            !"\t\t << Found TokenTree `{}`",
-            tt.to_token_stream().to_string()
-        );
-        if let Some(t) = tt {
-            let ts = t.to_token_stream();
-            let e: Option<E> = syn::parse2::<E>(ts).ok();
-            if t.type_id() == TypeId::of::<E>() || e.is_some() {
+        let parsed = fork.parse::<T>();
+        match fork.cursor().partial_cmp(&end) {
+            Some(Ordering::Equal) => {
+                if let Ok(val) = parsed {
+                    input.advance_to(&fork);
+                    return Ok(val);
+                }
+            },
+            Some(Ordering::Less) => {},
+            // Past `end`, or off the input, as `TokenStream2` leaves an empty
+            // cursor of its own
+            _ => *live = false,
+        }
+    }
+    let mut tokens = TokenStream2::new();
+    while !input.is_empty() && input.cursor() != end {
+        tokens.extend(Some(input.parse::<TokenTree>()?));
+    }
+    syn::parse2(tokens)
+}
+
+/// The span of the tokens from `start` up to (not including) `end`, on the
+/// same level, see `skip_until_peek`.
+pub(crate) fn span_between(mut start: Cursor, end: Cursor) -> Span {
+    let first = start.span();
+    let mut last = first;
+    while start != end {
+        match start.token_tree() {
+            Some((_, rest)) => {
+                last = start.span();
+                start = rest;
+            },
+            None => break,
+        }
+    }
+    first.join(last).unwrap_or(first)
+}
+
+/// The tokens from `start` up to (not including) `end`, on the same level.
+fn tokens_between(mut start: Cursor, end: Cursor) -> TokenStream2 {
+    let mut tokens = TokenStream2::new();
+    while start != end {
+        match start.token_tree() {
+            Some((next, rest)) => {
+                tokens.extend(Some(next));
+                start = rest;
+            },
+            None => break,
+        }
+    }
+    tokens
+}
+
+/// Parses the tokens up to the first one on another line than the token
+/// before it, that is not nested (see `parse_until`), e.g the first line of
+/// ```text
+/// a: HashMap<K,
+///     V>
+/// b: u8
+/// ```
+/// Errors when the tokens have no source locations, as the tokens made by
+/// `quote!` or `parse_quote!`, which all share the span of the macro call.
+pub fn parse_until_newline(input: ParseStream) -> syn::Result<TokenStream2> {
+    let mut tokens = TokenStream2::new();
+    let mut angles = Angles::default();
+    let mut nesting = Nesting::new(&mut angles);
+    let mut prev: Option<Span> = None;
+    while !input.is_empty() {
+        let cursor = input.cursor();
+        let span = cursor.span();
+        if let Some(prev) = prev {
+            if !has_location(prev, span) {
+                return Err(syn::Error::new(
+                    span,
+                    "Cannot separate these tokens by lines, as they have no source locations \
+                     (e.g they were made by `quote!`), separate them by delimiters instead",
+                ));
+            }
+            if nesting.is_top_level() && span.start().line > prev.end().line {
                 break;
             }
         }
         let next: TokenTree = input.parse()?;
+        nesting.step(cursor);
+        prev = Some(next.span());
         tokens.extend(Some(next));
     }
     Ok(tokens)
 }
 
+/// Whether the spans of two consecutive tokens tell where they are in the
+/// source, which they don't when they are the same (as the call site of a
+/// macro), or when span locations are not available at all.
+fn has_location(prev: Span, next: Span) -> bool {
+    next.start().line != 0 && (prev.start() != next.start() || prev.end() != next.end())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Nest {
+    Angles,
+    ClosureParams,
+}
+
+/// Tracks the `<..>` and `|..|` that are not token groups, but still nest the
+/// tokens in them, token by token.
+struct Nesting<'a, 'b> {
+    stack:  Vec<Nest>,
+    /// The last two tokens, the last one first.
+    prev:   [Option<Cursor<'a>>; 2],
+    /// Puncts to skip, as they are the rest of an operator, e.g the second `>`
+    /// of `>>`.
+    skip:   usize,
+    angles: &'b mut Angles<'a>,
+}
+
+impl<'a, 'b> Nesting<'a, 'b> {
+    fn new(angles: &'b mut Angles<'a>) -> Self {
+        Nesting {
+            stack: Vec::new(),
+            prev: [None, None],
+            skip: 0,
+            angles,
+        }
+    }
+
+    fn is_top_level(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Whether the last token forms an operator with the next one, as the `=`
+    /// of `=>`.
+    fn joins_next(&self, rest: Cursor) -> bool {
+        match (self.prev_punct(0), rest.punct()) {
+            (Some((c, Spacing::Joint)), Some((next, _))) => joins(c, next.as_char()),
+            _ => false,
+        }
+    }
+
+    /// Takes the token at `at` into account.
+    fn step(&mut self, at: Cursor<'a>) {
+        if let Some((punct, rest)) = at.punct() {
+            if self.skip > 0 {
+                self.skip -= 1;
+            } else {
+                self.step_punct(&punct, rest);
+            }
+        }
+        self.prev = [Some(at), self.prev[0]];
+    }
+
+    fn step_punct(&mut self, punct: &Punct, rest: Cursor<'a>) {
+        let joined = |c: char| {
+            punct.spacing() == Spacing::Joint && matches!(rest.punct(), Some((next, _)) if next.as_char() == c)
+        };
+        match punct.as_char() {
+            // `<=` and `<<`
+            '<' if joined('=') || joined('<') => self.skip = 1,
+            '<' if self.opens_angles(rest) => self.stack.push(Nest::Angles),
+            // `->` and `=>`
+            '>' if matches!(self.prev_punct(0), Some(('-' | '=', Spacing::Joint))) => {},
+            '>' if self.stack.last() == Some(&Nest::Angles) => {
+                self.stack.pop();
+            },
+            // `>>` out of generics
+            '>' if joined('>') => self.skip = 1,
+            '|' if self.stack.last() == Some(&Nest::ClosureParams) => {
+                self.stack.pop();
+            },
+            // `||`, either an operator or a closure without params
+            '|' if joined('|') => self.skip = 1,
+            '|' if self.starts_expr() => self.stack.push(Nest::ClosureParams),
+            _ => {},
+        }
+    }
+
+    fn prev_punct(&self, idx: usize) -> Option<(char, Spacing)> {
+        let (punct, _) = self.prev[idx]?.punct()?;
+        Some((punct.as_char(), punct.spacing()))
+    }
+
+    fn prev_ident_is(&self, keywords: &[&str]) -> bool {
+        match self.prev[0].and_then(|prev| prev.ident()) {
+            Some((ident, _)) => keywords.iter().any(|keyword| ident == keyword),
+            None => false,
+        }
+    }
+
+    /// Whether an expression may start at the next token, e.g a closure after
+    /// `=` or `move`, but not after `a` (where `|` is a bitwise or).
+    fn starts_expr(&self) -> bool {
+        match self.prev[0] {
+            None => true,
+            Some(prev) => prev.punct().is_some() || self.prev_ident_is(&["move", "return"]),
+        }
+    }
+
+    /// Whether the `<` that was just stepped over opens generics, rather than
+    /// being a comparison.
+    fn opens_angles(&mut self, rest: Cursor<'a>) -> bool {
+        // `::<` and `<T as Trait>::` after an operator or at the start
+        if matches!(self.prev_punct(0), Some((':', _))) && matches!(self.prev_punct(1), Some((':', _))) {
+            return true;
+        }
+        match self.prev[0] {
+            None => true,
+            Some(prev) if prev.punct().is_some() => true,
+            Some(prev) if prev.ident().is_some() => {
+                self.prev_ident_is(&["impl", "for", "fn"]) || self.angles.closes_as_generics(rest)
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Whether a whole `E` is next in the input, i.e it parses as `E`, and the
+/// last punct of it is not joined to a following punct (as the `=` of `==` or
+/// `=>`, for `E = Token![=]`). Only forks the input when `E::peek` finds one.
+pub fn peek_delim<E: Delimiter>(input: ParseStream) -> bool {
+    if !E::peek(input.cursor()) {
+        return false;
+    }
+    let fork = input.fork();
+    if fork.parse::<E>().is_err() {
+        return false;
+    }
+    let end = fork.cursor();
+    let mut cursor = input.cursor();
+    let mut last = None;
+    while cursor != end {
+        let (punct, next) = match cursor.punct() {
+            Some((punct, next)) => (Some(punct), next),
+            None => match cursor.token_tree() {
+                Some((_, next)) => (None, next),
+                None => break,
+            },
+        };
+        last = punct;
+        cursor = next;
+    }
+    match (last, end.punct()) {
+        (Some(p), Some((next, _))) if p.spacing() == Spacing::Joint => !joins(p.as_char(), next.as_char()),
+        _ => true,
+    }
+}
+
+/// Whether the puncts `a` and `b` form (part of) an operator when joined, e.g
+/// `=` and `>`, but not `>` and `,` (which are joined in `Vec<u8>,`).
+fn joins(a: char, b: char) -> bool {
+    matches!(
+        (a, b),
+        ('&' | '|' | '=' | '<' | '>' | ':' | '.', _) if a == b
+    ) || matches!(
+        (a, b),
+        ('&' | '|' | '^' | '!' | '=' | '<' | '>' | '+' | '-' | '*' | '/' | '%' | '.', '=')
+            | ('-' | '=', '>')
+    )
+}
+
+
+/// The lookahead for whether the `<` of a token stream open generics (see
+/// `Angles::closes_as_generics`), done in a single pass for all the `<` that
+/// are nested in the first one, and kept for when the parsing gets to them.
+#[derive(Default)]
+pub(crate) struct Angles<'a> {
+    /// The cursor right after each `<` looked ahead of, in order, and whether
+    /// the `<` opens generics.
+    closes: Vec<(Cursor<'a>, bool)>,
+}
+
+impl<'a> Angles<'a> {
+    /// Whether the tokens after a `<` are closed by a `>` with only tokens that
+    /// may appear in generics in between, and the `>` is not followed by
+    /// tokens on the same line that may not follow a type (as in
+    /// `a < b, c > d`).
+    fn closes_as_generics(&mut self, rest: Cursor<'a>) -> bool {
+        let idx = match self
+            .closes
+            .binary_search_by(|(at, _)| at.partial_cmp(&rest).unwrap_or(Ordering::Less))
+        {
+            Ok(idx) => return self.closes[idx].1,
+            Err(idx) => idx,
+        };
+        let found = look_ahead_angles(rest);
+        let result = found[0].1;
+        self.closes.splice(idx..idx, found);
+        result
+    }
+}
+
+/// Walks the tokens after the `<` at `rest` until it is closed, keeping track
+/// of every `<` nested in it on the way, as the `>` closing those and the
+/// tokens that can't be in generics are found in the same walk. Returns the
+/// cursor after each `<` (in order, starting with `rest`), and whether it
+/// closes as generics. A `<` still open when the walk stops doesn't.
+fn look_ahead_angles(rest: Cursor) -> Vec<(Cursor, bool)> {
+    let mut found = vec![(rest, false)];
+    let mut open = vec![0usize];
+    let mut prev: Option<(char, Spacing)> = None;
+    let mut cursor = rest;
+    while !cursor.eof() {
+        let (punct, next) = match cursor.punct() {
+            Some(found) => found,
+            None => {
+                prev = None;
+                cursor = match cursor.token_tree() {
+                    Some((_, next)) => next,
+                    None => break,
+                };
+                continue;
+            },
+        };
+        let c = punct.as_char();
+        match (prev, c) {
+            // `&&`, `==` and `!=`
+            (Some(('&', Spacing::Joint)), '&') | (Some(('=' | '!', Spacing::Joint)), '=') => break,
+            (Some(('-', Spacing::Joint)), '>') => {},
+            // `>=`, and `>>` when there is a single `<` for it to close
+            (_, '>') if punct.spacing() == Spacing::Joint && is_operator_after_gt(next, open.len()) => break,
+            (_, '>') => {
+                let idx = open.pop().expect("Expected an open `<`");
+                found[idx].1 = closes_before(&punct, next);
+                if open.is_empty() {
+                    break;
+                }
+            },
+            (_, '<') => {
+                found.push((next, false));
+                open.push(found.len() - 1);
+            },
+            (_, ':' | ',' | '&' | '*' | '\'' | '+' | '=' | '-' | '!' | '?') => {},
+            _ => break,
+        }
+        prev = Some((c, punct.spacing()));
+        cursor = next;
+    }
+    found
+}
+
+/// Whether the punct joined to a `>` (at `next`) makes it an operator rather
+/// than the end of generics, `open` being the number of `<` still open.
+fn is_operator_after_gt(next: Cursor, open: usize) -> bool {
+    match next.punct() {
+        Some((punct, _)) => punct.as_char() == '=' || (punct.as_char() == '>' && open < 2),
+        None => false,
+    }
+}
+
+/// Whether the tokens after the `>` at the end of generics may follow a type.
+fn closes_before(punct: &Punct, next: Cursor) -> bool {
+    let follows_type = match next.ident() {
+        Some((ident, _)) => ident == "as" || ident == "where",
+        None => next.literal().is_none(),
+    };
+    // A following line may start with anything, see `parse_until_newline`.
+    // Only checked when needed, as the locations are slow to get outside of a
+    // proc macro
+    follows_type || next.span().start().line > punct.span().end().line
+}
+
 pub fn parse_peeks_until<E: Token + Clone + ToTokens + 'static>(
     input: ParseStream,
     end: E,
@@ -409,7 +827,7 @@ macro_rules! try_get_tuple_params {
                 }
                 let tuple_args_stream;
                 syn::parenthesized!(tuple_args_stream in $input);
-                $var = tuple_args_stream.parse().ok();
+                $var = Some(tuple_args_stream.parse()?);
             }
         }
     };
@@ -437,7 +855,7 @@ macro_rules! try_get_trails {
             }
             $crate::__pmmh_debug_file!(!"Trails were valid! What remains is: {}", $input);
             let ts = parse_peekables_until($input, syn::token::Brace)?;
-            $var = syn::parse2::<$punct_set>(ts).ok();
+            $var = Some(syn::parse2::<$punct_set>(ts)?);
         }
     };
 }
@@ -518,3 +936,59 @@ macro_rules! surround {
         });
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use syn::parse::{ParseStream, Parser};
+    use syn::{token, Token};
+
+    use super::parse_until;
+    use crate::TokenStream2;
+
+    /// Splits `input` on the top-level `,` with `parse_until`.
+    fn split(input: &str) -> Vec<String> {
+        let parser = |input: ParseStream| {
+            let mut parts = Vec::new();
+            while !input.is_empty() {
+                parts.push(parse_until(input, token::Comma::default())?.to_string());
+                if !input.is_empty() {
+                    input.parse::<Token![,]>()?;
+                }
+            }
+            Ok(parts)
+        };
+        parser.parse_str(input).unwrap()
+    }
+
+    fn tokens(parts: &[&str]) -> Vec<String> {
+        parts
+            .iter()
+            .map(|part| part.parse::<TokenStream2>().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn generics() {
+        assert_eq!(split("HashMap<K, V>, u8"), tokens(&["HashMap<K, V>", "u8"]));
+        assert_eq!(split("Vec<Vec<u8>>, u8"), tokens(&["Vec<Vec<u8>>", "u8"]));
+        assert_eq!(split("Vec<Vec<Vec<u8>>>, u8"), tokens(&["Vec<Vec<Vec<u8>>>", "u8"]));
+        assert_eq!(split("Box<dyn Fn(u8) -> u8>, u8"), tokens(&["Box<dyn Fn(u8) -> u8>", "u8"]));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(split("a < b, c > d"), tokens(&["a < b", "c > d"]));
+        assert_eq!(split("x >> 2, y"), tokens(&["x >> 2", "y"]));
+        assert_eq!(split("x << 2, y > 1"), tokens(&["x << 2", "y > 1"]));
+        assert_eq!(split("a < b, c >= d"), tokens(&["a < b", "c >= d"]));
+        assert_eq!(split("a < b, c >> d"), tokens(&["a < b", "c >> d"]));
+        assert_eq!(split("a < b, c >>= d"), tokens(&["a < b", "c >>= d"]));
+    }
+
+    #[test]
+    fn closures() {
+        assert_eq!(split("|a, b| a + b, c"), tokens(&["|a, b| a + b", "c"]));
+        assert_eq!(split("a | b, c"), tokens(&["a | b", "c"]));
+        assert_eq!(split("Fn(u8) -> u8, c"), tokens(&["Fn(u8) -> u8", "c"]));
+    }
+}
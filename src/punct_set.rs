@@ -1,64 +1,431 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter, Pointer, Write};
+use std::ops::{Index, IndexMut};
 
 use debug_helpers::debug_file;
 use derive_display::derive_display;
 use paste::paste;
-use proc_macro2::TokenTree;
-use quote::{quote, ToTokens, TokenStreamExt};
+use proc_macro2::Span;
+use quote::{ToTokens, TokenStreamExt};
+use syn::buffer::Cursor;
+use syn::parse::discouraged::Speculative;
 use syn::parse::{Parse, Parser, Peek};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
 use syn::token::Token;
 use syn::Token;
 
-use crate::parse_utils::parse_until;
+use crate::parse_utils::{
+    parse_until,
+    parse_until_newline,
+    parse_up_to,
+    peek_delim,
+    push_error,
+    skip_until_peek,
+    span_between,
+    Angles,
+};
 use crate::{token_name, unwrap_input, TokenStream2};
 
 /// Any punctuated set of instances of type T, delimited by a token of type D
 /// e.g
-/// ```
+/// ```text
 /// here, is, stuff, de, limited, by_commas, each_being_an_ident
 /// This + Is + Types + Delimited + By + Pluses
 /// a::b::c::d::e::f::g
 /// x?y?z
 /// ```
+/// Every element keeps the delimiter that followed it (if any) and its span,
+/// so that the set can be re-emitted as it was, and errors can point at a
+/// single element. The set may be empty, and may end with a trailing delimiter
+/// (see `TrailingPolicy` to forbid or require one).
 #[derive(Clone)]
 pub struct PunctSet<
     T: Parse + Display + ToTokens = TokenStream2,
-    D: Token + Parse + Default = Token![,],
+    D: Delimiter = Token![,],
 > {
-    pub vals:  Vec<T>,
-    pub delim: Option<D>,
+    pub vals:   Vec<T>,
+    /// The delimiter after each element, the last one being the trailing
+    /// delimiter.
+    pub delims: Vec<Option<D>>,
+    pub spans:  Vec<Span>,
 }
 
-#[derive_display]
-impl<T: Parse + Display + ToTokens + Clone, D: Token + Parse + Default + ToTokens> ToTokens
-    for PunctSet<T, D>
-{
+/// A token that may delimit the elements of a `PunctSet`, i.e any syn token
+/// (e.g `Token![,]`), a keyword (see `kw`), or an `Alt` of them.
+pub trait Delimiter: Parse + Default {
+    /// Whether the next token starts this delimiter, see `peek_delim` for
+    /// whether the whole delimiter is there.
+    fn peek(cursor: Cursor) -> bool;
+
+    /// The delimiter as shown in errors, e.g "`,`" or "`,` or `;`".
+    fn display() -> Cow<'static, str>;
+}
+
+impl<T: Token + Parse + Default> Delimiter for T {
+    fn peek(cursor: Cursor) -> bool {
+        <T as Token>::peek(cursor)
+    }
+
+    fn display() -> Cow<'static, str> {
+        Cow::Borrowed(<T as Token>::display())
+    }
+}
+
+/// Keywords that may delimit a `PunctSet`, e.g `PunctSet<Expr, kw::and>` for
+/// `a and b and c`.
+pub mod kw {
+    syn::custom_keyword!(and);
+    syn::custom_keyword!(or);
+}
+
+/// A delimiter that is either an `A` or a `B`, e.g `Alt<Token![,], Token![;]>`
+/// for `a, b; c`, or `Alt<A, Alt<B, C>>` for more alternatives. Elements that
+/// have no delimiter are delimited by `A` when emitted.
+#[derive(Clone)]
+pub enum Alt<A, B> {
+    A(A),
+    B(B),
+}
+
+impl<A: Default, B> Default for Alt<A, B> {
+    fn default() -> Self {
+        Alt::A(A::default())
+    }
+}
+
+impl<A: Delimiter, B: Parse> Parse for Alt<A, B> {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if peek_delim::<A>(input) {
+            Ok(Alt::A(input.parse()?))
+        } else {
+            Ok(Alt::B(input.parse()?))
+        }
+    }
+}
+
+impl<A: ToTokens, B: ToTokens> ToTokens for Alt<A, B> {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
-        let last = &self.vals.iter().last().expect(
-            "Expected the PunctSet to contain at least one \
-         element",
-        );
-        let mut vals: Vec<T> = Vec::new();
-        let len = self.vals.iter().count();
-        for (idx, val) in self.vals.iter().enumerate() {
-            if idx >= len - 1 {
-                break;
+        match self {
+            Alt::A(a) => a.to_tokens(tokens),
+            Alt::B(b) => b.to_tokens(tokens),
+        }
+    }
+}
+
+impl<A: Delimiter, B: Delimiter> Delimiter for Alt<A, B> {
+    fn peek(cursor: Cursor) -> bool {
+        A::peek(cursor) || B::peek(cursor)
+    }
+
+    fn display() -> Cow<'static, str> {
+        Cow::Owned(format!("{} or {}", A::display(), B::display()))
+    }
+}
+
+/// Whether a `PunctSet` may end with a delimiter, e.g `a, b, c,`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum TrailingPolicy {
+    Forbid,
+    #[default]
+    Allow,
+    /// A trailing delimiter is required after the last element, unless the
+    /// set is empty.
+    Require,
+}
+
+impl<T: Parse + Display + ToTokens, D: Delimiter> PunctSet<T, D> {
+    pub fn empty() -> Self {
+        PunctSet {
+            vals:   Vec::new(),
+            delims: Vec::new(),
+            spans:  Vec::new(),
+        }
+    }
+
+    /// The first delimiter of the set, which is also the one emitted between
+    /// elements that have none (e.g elements pushed to `vals` directly).
+    pub fn delim(&self) -> Option<&D> {
+        self.delims.iter().flatten().next()
+    }
+
+    /// The delimiter after the last element, if there is one.
+    pub fn trailing(&self) -> Option<&D> {
+        let last = self.vals.len().checked_sub(1)?;
+        self.delims.get(last)?.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vals.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.vals.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.vals.iter_mut()
+    }
+
+    /// Appends an element, which is delimited from the previous last element
+    /// by the delimiter of the set (see `delim`).
+    pub fn push(&mut self, val: T) {
+        let span = val.span();
+        self.push_spanned(val, None, span);
+    }
+
+    /// Appends an element with the delimiter that follows it and its span.
+    pub fn push_spanned(&mut self, val: T, delim: Option<D>, span: Span) {
+        self.align();
+        self.vals.push(val);
+        self.delims.push(delim);
+        self.spans.push(span);
+    }
+
+    /// Inserts an element at `idx`, shifting the ones after it.
+    pub fn insert(&mut self, idx: usize, val: T) {
+        self.align();
+        let span = val.span();
+        self.vals.insert(idx, val);
+        self.delims.insert(idx, None);
+        self.spans.insert(idx, span);
+    }
+
+    /// Removes the element at `idx` along with its delimiter and span.
+    pub fn remove(&mut self, idx: usize) -> T {
+        self.align();
+        let had_trailing = self.trailing().is_some();
+        self.delims.remove(idx);
+        self.spans.remove(idx);
+        let result = self.vals.remove(idx);
+        self.keep_trailing(had_trailing);
+        result
+    }
+
+    /// Keeps only the elements for which `f` returns true, along with their
+    /// delimiters and spans.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.align();
+        let had_trailing = self.trailing().is_some();
+        let vals = std::mem::take(&mut self.vals);
+        let delims = std::mem::take(&mut self.delims);
+        let spans = std::mem::take(&mut self.spans);
+        for ((val, delim), span) in vals.into_iter().zip(delims).zip(spans) {
+            if f(&val) {
+                self.vals.push(val);
+                self.delims.push(delim);
+                self.spans.push(span);
             }
-            vals.push(val.clone());
         }
-        let d = &self.delim;
-        if vals.len() > 0 {
-            let q = quote!(#(#vals #d)*);
-            q.to_tokens(tokens);
+        self.keep_trailing(had_trailing);
+    }
+
+    /// Elements pushed to `vals` directly are given no delimiter and the span
+    /// of their tokens, so that `delims` and `spans` stay aligned with `vals`.
+    fn align(&mut self) {
+        self.delims.resize_with(self.vals.len(), || None);
+        for idx in self.spans.len()..self.vals.len() {
+            let span = self.vals[idx].span();
+            self.spans.push(span);
+        }
+        self.spans.truncate(self.vals.len());
+    }
+
+    /// Once the last element was removed, the delimiter of the new last one
+    /// would otherwise become a trailing delimiter.
+    fn keep_trailing(&mut self, had_trailing: bool) {
+        if !had_trailing {
+            if let Some(last) = self.delims.last_mut() {
+                *last = None;
+            }
         }
-        last.to_tokens(tokens);
     }
 }
 
-impl<T: Parse + Display + ToTokens + 'static, D: Token + Parse + Default + 'static>
-    syn::parse::Parse for PunctSet<T, D>
+impl<T: Parse + Display + ToTokens, D: Delimiter> Index<usize> for PunctSet<T, D> {
+    type Output = T;
+
+    fn index(&self, idx: usize) -> &T {
+        &self.vals[idx]
+    }
+}
+
+impl<T: Parse + Display + ToTokens, D: Delimiter> IndexMut<usize> for PunctSet<T, D> {
+    fn index_mut(&mut self, idx: usize) -> &mut T {
+        &mut self.vals[idx]
+    }
+}
+
+impl<T: Parse + Display + ToTokens, D: Delimiter> IntoIterator for PunctSet<T, D> {
+    type IntoIter = std::vec::IntoIter<T>;
+    type Item = T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vals.into_iter()
+    }
+}
+
+impl<'a, T: Parse + Display + ToTokens, D: Delimiter> IntoIterator for &'a PunctSet<T, D> {
+    type IntoIter = std::slice::Iter<'a, T>;
+    type Item = &'a T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vals.iter()
+    }
+}
+
+impl<'a, T: Parse + Display + ToTokens, D: Delimiter> IntoIterator for &'a mut PunctSet<T, D> {
+    type IntoIter = std::slice::IterMut<'a, T>;
+    type Item = &'a mut T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vals.iter_mut()
+    }
+}
+
+impl<T: Parse + Display + ToTokens, D: Delimiter> FromIterator<T> for PunctSet<T, D> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut result = Self::empty();
+        result.extend(iter);
+        result
+    }
+}
+
+impl<T: Parse + Display + ToTokens, D: Delimiter> Extend<T> for PunctSet<T, D> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for val in iter {
+            self.push(val);
+        }
+    }
+}
+
+/// Keeps every delimiter, including a trailing one, and takes the spans of
+/// the elements from their tokens.
+impl<T: Parse + Display + ToTokens, D: Delimiter> From<Punctuated<T, D>> for PunctSet<T, D> {
+    fn from(punctuated: Punctuated<T, D>) -> Self {
+        let mut result = Self::empty();
+        for pair in punctuated.into_pairs() {
+            let (val, delim) = pair.into_tuple();
+            let span = val.span();
+            result.push_spanned(val, delim, span);
+        }
+        result
+    }
+}
+
+/// Keeps every delimiter, including a trailing one. Elements without a
+/// delimiter before another element (e.g pushed to `vals` directly) are
+/// delimited by `D::default()`.
+impl<T: Parse + Display + ToTokens, D: Delimiter> From<PunctSet<T, D>> for Punctuated<T, D> {
+    fn from(set: PunctSet<T, D>) -> Self {
+        let mut result = Punctuated::new();
+        let mut delims = set.delims.into_iter();
+        for val in set.vals {
+            if !result.empty_or_trailing() {
+                result.push_punct(D::default());
+            }
+            result.push_value(val);
+            if let Some(Some(delim)) = delims.next() {
+                result.push_punct(delim);
+            }
+        }
+        result
+    }
+}
+
+impl<T: Parse + Display + ToTokens, D: Delimiter + ToTokens> PunctSet<T, D> {
+    /// The span the element at `idx` was parsed from, or the span of its
+    /// tokens for elements that weren't parsed.
+    pub fn span_of(&self, idx: usize) -> Option<Span> {
+        self.spans
+            .get(idx)
+            .copied()
+            .or_else(|| self.vals.get(idx).map(|val| val.span()))
+    }
+
+    /// Iterates the elements together with their spans, see `span_of`.
+    pub fn iter_spanned(&self) -> impl Iterator<Item = (&T, Span)> {
+        self.vals
+            .iter()
+            .enumerate()
+            .map(|(idx, val)| (val, self.span_of(idx).unwrap_or_else(Span::call_site)))
+    }
+
+    /// The span of the whole set, from its first element to its trailing
+    /// delimiter (where joining spans is supported, otherwise just the span of
+    /// the first element).
+    pub fn span(&self) -> Span {
+        let last_idx = match self.vals.len().checked_sub(1) {
+            Some(last_idx) => last_idx,
+            None => return Span::call_site(),
+        };
+        let first = match self.span_of(0) {
+            Some(first) => first,
+            None => return Span::call_site(),
+        };
+        let last = match self.trailing() {
+            Some(trailing) => trailing.span(),
+            None => self.span_of(last_idx).unwrap_or(first),
+        };
+        first.join(last).unwrap_or(first)
+    }
+}
+
+impl<T: Parse + Display + ToTokens + 'static, D: Delimiter + ToTokens + 'static>
+    PunctSet<T, D>
 {
-    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+    /// Parses the set, erroring when its trailing delimiter doesn't follow
+    /// `policy`. Parsing the set with `parse` is the same as using
+    /// `TrailingPolicy::Allow`.
+    pub fn parse_with(input: syn::parse::ParseStream, policy: TrailingPolicy) -> syn::Result<Self> {
+        let result: Self = input.parse()?;
+        result.check_trailing(policy)?;
+        Ok(result)
+    }
+
+    pub fn check_trailing(&self, policy: TrailingPolicy) -> syn::Result<()> {
+        match (policy, self.trailing(), self.vals.last()) {
+            (TrailingPolicy::Forbid, Some(trailing), _) => Err(syn::Error::new_spanned(
+                trailing,
+                format!("Unexpected trailing {}", D::display()),
+            )),
+            (TrailingPolicy::Require, None, Some(last)) => Err(syn::Error::new_spanned(
+                last,
+                format!("Expected a trailing {} after this", D::display()),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive_display]
+impl<T: Parse + Display + ToTokens, D: Delimiter + ToTokens> ToTokens for PunctSet<T, D> {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let default_delim = D::default();
+        let fallback = self.delim().unwrap_or(&default_delim);
+        let last = self.vals.len().saturating_sub(1);
+        for (idx, val) in self.vals.iter().enumerate() {
+            val.to_tokens(tokens);
+            match self.delims.get(idx) {
+                Some(Some(delim)) => delim.to_tokens(tokens),
+                _ if idx < last => fallback.to_tokens(tokens),
+                _ => {},
+            }
+        }
+    }
+}
+
+impl<T: Parse + Display + ToTokens + 'static, D: Delimiter + 'static> PunctSet<T, D> {
+    /// Parses the set like `parse`, but carries on past the elements that
+    /// fail to parse, returning the set of the other elements along with the
+    /// errors of the failing ones, all combined. Only the errors that prevent
+    /// finding the elements at all are returned as `Err`.
+    pub fn parse_lenient(input: syn::parse::ParseStream) -> syn::Result<(Self, Option<syn::Error>)> {
         debug_file!(
             input,
             format!(
@@ -71,45 +438,310 @@ impl<T: Parse + Display + ToTokens + 'static, D: Token + Parse + Default + 'stat
         // let mut streams: Punctuated<TokenStream2, D> =
         // Punctuated::parse_separated_nonempty(input)?;
         unwrap_input!(input, unwrapped_input);
-        let mut vec: Vec<T> = Vec::new();
-        let mut delim: Option<D> = None;
+        let mut result = Self::empty();
+        let mut errors = None;
+        let mut angles = Angles::default();
+        // Whether `T` is parsed from the input itself (see `parse_up_to`), and
+        // whether it was seen stopping right at the end of an element
+        let mut live = true;
+        let mut checked = false;
         while !unwrapped_input.is_empty() {
-            let (token, next) = unwrapped_input.cursor().token_tree().unwrap();
-            let punct: Option<D> = syn::parse2(token.to_token_stream()).ok();
-            if next.eof() && punct.is_some() {
-                break;
+            if peek_delim::<D>(unwrapped_input) {
+                let fork = unwrapped_input.fork();
+                let _: D = fork.parse()?;
+                if fork.is_empty() {
+                    break;
+                }
             }
             debug_file!(!"Processing unwrapped_input `{}`", unwrapped_input);
-            let stream: TokenStream2 = parse_until(unwrapped_input, D::default())?;
-            debug_file!(
-                !"Parsed until {}, what remains is: `{}`",
-                D::display(),
-                stream
-            );
-            let val: Option<T> = syn::parse2::<T>(stream.clone()).ok();
-            if val.is_none() {
-                let actual = syn::parse2::<TokenTree>(stream.clone()).ok().expect(format!("Found no `{}`, but expected there to be at least some TokenTree left in: `{}`",
-                                                                                          token_name!(ty T),
-                                                                                          stream
-                                                                                              .to_string()).as_str());
-                panic!(
-                    "Expected a {}, but found {}",
-                    token_name!(ty T),
-                    token_name!(parsable actual)
-                );
+            let start = unwrapped_input.cursor();
+            // Once checked, the element is parsed in a single pass, and kept
+            // when it ends at a delimiter
+            let in_place = live && checked;
+            if in_place {
+                let fork = unwrapped_input.fork();
+                if let Ok(val) = fork.parse::<T>() {
+                    match fork.cursor().partial_cmp(&start) {
+                        Some(Ordering::Greater) if fork.is_empty() || peek_delim::<D>(&fork) => {
+                            let span = span_between(start, fork.cursor());
+                            unwrapped_input.advance_to(&fork);
+                            let next_delim: Option<D> = unwrapped_input.parse().ok();
+                            result.push_spanned(val, next_delim, span);
+                            continue;
+                        },
+                        Some(_) => {},
+                        None => live = false,
+                    }
+                }
+            }
+            // Otherwise the end of the element is found first, to check `T`
+            // against it, or to report the error of `T` on its own tokens
+            let end = unwrapped_input.fork();
+            let span = skip_until_peek(&end, peek_delim::<D>, &mut angles)?;
+            let parsed = if end.cursor() == start {
+                Err(syn::Error::new(
+                    span,
+                    format!("Expected a {} before {}", token_name!(ty T), D::display()),
+                ))
+            } else if in_place {
+                parse_up_to::<T>(unwrapped_input, end.cursor(), &mut false)
+            } else {
+                let parsed = parse_up_to::<T>(unwrapped_input, end.cursor(), &mut live);
+                checked = live && parsed.is_ok();
+                parsed
+            };
+            let next_delim: Option<D> = unwrapped_input.parse().ok();
+            match parsed {
+                Ok(v) => {
+                    debug_file!(!"Found stream `{}`", v);
+                    result.vals.push(v);
+                    result.delims.push(next_delim);
+                    result.spans.push(span);
+                },
+                // Skips to the next element, which `parse_until` stopped at
+                Err(e) => push_error(&mut errors, e),
+            }
+        }
+        debug_file!(result, "Result of parsing PunctSet below:");
+        Ok((result, errors))
+    }
+}
+
+impl<T: Parse + Display + ToTokens + 'static, D: Delimiter + 'static> PunctSet<T, D> {
+    /// Parses the set with each line being an element, instead of delimiting
+    /// the elements by `D`, e.g
+    /// ```text
+    /// primary: red
+    /// secondary: Color::rgb(0, 0,
+    ///     255),
+    /// ```
+    /// A `D` at the end of a line is kept as the delimiter of its element, and
+    /// emitted as is, while the other elements are delimited by `D` when
+    /// emitted. Errors when the tokens have no source locations (see
+    /// `parse_until_newline`), unless the rest of them is a single element.
+    pub fn parse_lines(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        unwrap_input!(input, unwrapped_input);
+        let mut result = Self::empty();
+        let mut errors = None;
+        let parse_line = |input: syn::parse::ParseStream| -> syn::Result<(T, Option<D>)> {
+            let val: T = syn::parse2(parse_until(input, D::default())?)?;
+            if input.is_empty() {
+                return Ok((val, None));
+            }
+            let delim: D = input.parse()?;
+            if !input.is_empty() {
+                return Err(input.error(format!("Expected a new line after {}", D::display())));
             }
-            let v = val.unwrap();
-            debug_file!(!"Found stream `{}`", v);
-            if delim.is_none() {
-                delim = unwrapped_input.parse().ok();
-                debug_file!(!"Found a punct `{:?}`", D::display());
+            Ok((val, Some(delim)))
+        };
+        while !unwrapped_input.is_empty() {
+            let fork = unwrapped_input.fork();
+            let line = match parse_until_newline(&fork) {
+                Ok(line) => {
+                    unwrapped_input.advance_to(&fork);
+                    line
+                },
+                // Without locations, the rest is taken as a single line, which
+                // only works out when it is a single element
+                Err(e) => {
+                    let rest: TokenStream2 = unwrapped_input.parse()?;
+                    if parse_line.parse2(rest.clone()).is_err() {
+                        return Err(e);
+                    }
+                    rest
+                },
+            };
+            debug_file!(!"Found line `{}`", line);
+            let span = line.span();
+            match parse_line.parse2(line) {
+                Ok((val, delim)) => result.push_spanned(val, delim, span),
+                Err(e) => push_error(&mut errors, e),
             }
-            vec.push(v);
         }
-        debug_file!(quote!(#(#vec),*), "Result of parsing PunctSet below:");
-        Ok(Self {
-            vals: vec,
-            delim,
-        })
+        debug_file!(result, "Result of parsing PunctSet by lines below:");
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(result),
+        }
+    }
+}
+
+impl<T: Parse + Display + ToTokens + 'static, D: Delimiter + 'static>
+    syn::parse::Parse for PunctSet<T, D>
+{
+    /// Errors with the errors of all the elements that fail to parse.
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        match Self::parse_lenient(input)? {
+            (result, None) => Ok(result),
+            (_, Some(e)) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proc_macro2::Span;
+    use quote::{quote, ToTokens};
+    use syn::parse::{ParseStream, Parser};
+    use syn::punctuated::Punctuated;
+    use syn::{Ident, LitInt, Token};
+
+    use super::{kw, Alt, Delimiter, PunctSet, TrailingPolicy};
+    use crate::declaration::Declaration;
+    use crate::define::{Define, Defines};
+    use crate::TokenStream2;
+
+    fn parse_with(input: &str, policy: TrailingPolicy) -> syn::Result<PunctSet<Ident>> {
+        (|input: ParseStream| PunctSet::parse_with(input, policy)).parse_str(input)
+    }
+
+    #[test]
+    fn trailing() {
+        let set: PunctSet<Ident> = syn::parse_str("a, b, c,").unwrap();
+        assert_eq!(set.to_token_stream().to_string(), "a , b , c ,");
+        assert!(set.trailing().is_some());
+        let set: PunctSet<Ident> = syn::parse_str("a, b").unwrap();
+        assert!(set.trailing().is_none());
+        assert_eq!(syn::parse_str::<PunctSet<Ident>>("a,").unwrap().to_string(), "a ,");
+
+        let err = parse_with("a, b,", TrailingPolicy::Forbid).err().unwrap();
+        assert_eq!(err.to_string(), "Unexpected trailing `,`");
+        let err = parse_with("a, b", TrailingPolicy::Require).err().unwrap();
+        assert_eq!(err.to_string(), "Expected a trailing `,` after this");
+        assert!(parse_with("", TrailingPolicy::Require).unwrap().is_empty());
+
+        let declaration: Declaration = syn::parse_str("Foo(a: u8,)").unwrap();
+        assert_eq!(declaration.to_token_stream().to_string(), "Foo (a : u8 ,)");
+    }
+
+    #[test]
+    fn empty() {
+        let set: PunctSet<Ident> = syn::parse_str("").unwrap();
+        assert!(set.is_empty());
+        assert_eq!(set.to_token_stream().to_string(), "");
+        assert_eq!(PunctSet::<Ident>::empty().to_string(), "");
+        assert_eq!(syn::parse_str::<Defines>("").unwrap().to_string(), "");
+        let declaration: Declaration = syn::parse_str("Foo()").unwrap();
+        assert!(declaration.params.unwrap().is_empty());
+    }
+
+    #[test]
+    fn delims_and_spans() {
+        let set: PunctSet<Ident> = syn::parse_str("a, b, c").unwrap();
+        assert_eq!(set.delims.len(), 3);
+        assert!(set.delims[..2].iter().all(|d| d.is_some()));
+        assert!(set.trailing().is_none());
+        let (third, span) = set.iter_spanned().nth(2).unwrap();
+        assert_eq!(third, "c");
+        assert_eq!(span.start().column, 6);
+        assert_eq!(set.span().start().column, 0);
+        assert!(set.span_of(3).is_none());
+        PunctSet::<Ident>::empty().span();
+
+        let mut defines: Defines = syn::parse_str("a: 1, b: 2, c: 3,").unwrap();
+        defines.take("b").unwrap();
+        assert_eq!(defines.to_token_stream().to_string(), "a : 1 , c : 3 ,");
+        defines.take("c").unwrap();
+        assert_eq!(defines.to_token_stream().to_string(), "a : 1 ,");
+
+        let mut set: PunctSet<Ident> = syn::parse_str("a, b").unwrap();
+        set.vals.push(syn::parse_str("c").unwrap());
+        set.push_spanned(syn::parse_str("d").unwrap(), None, Span::call_site());
+        assert_eq!(set.to_string(), "a , b , c , d");
+        assert_eq!(set.delims.len(), 4);
+        assert_eq!(set.spans.len(), 4);
+    }
+
+    #[test]
+    fn collection() {
+        let mut set: PunctSet<LitInt> = syn::parse_str("1, 2, 3, 4").unwrap();
+        assert_eq!(set.len(), 4);
+        set.retain(|l| l.base10_parse::<u8>().unwrap() % 2 == 0);
+        assert_eq!(set.to_token_stream().to_string(), "2 , 4");
+        set.push(syn::parse_str("6").unwrap());
+        set.insert(0, syn::parse_str("0").unwrap());
+        assert_eq!(set.to_token_stream().to_string(), "0 , 2 , 4 , 6");
+        assert_eq!(set.remove(3).to_string(), "6");
+        assert_eq!(set.to_token_stream().to_string(), "0 , 2 , 4");
+        assert_eq!(set[1].to_string(), "2");
+        for val in &mut set {
+            *val = syn::parse_str("9").unwrap();
+        }
+        let punctuated: Punctuated<LitInt, Token![,]> = set.into();
+        assert_eq!(punctuated.to_token_stream().to_string(), "9 , 9 , 9");
+    }
+
+    #[test]
+    fn conversions() {
+        let set: PunctSet<LitInt> = syn::parse_str("1, 2,").unwrap();
+        let punctuated: Punctuated<LitInt, Token![,]> = set.into();
+        assert!(punctuated.trailing_punct());
+        let back: PunctSet<LitInt> = punctuated.into();
+        assert_eq!(back.to_token_stream().to_string(), "1 , 2 ,");
+
+        let idents: PunctSet<Ident> = ["a", "b"].iter().map(|s| Ident::new(s, Span::call_site())).collect();
+        assert_eq!(idents.to_token_stream().to_string(), "a , b");
+        assert_eq!(idents.into_iter().count(), 2);
+    }
+
+    #[test]
+    fn recovery() {
+        let err = syn::parse_str::<PunctSet<LitInt>>(r#"1, x, 3, , "s", 6"#).err().unwrap();
+        let errors: Vec<String> = err.into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, [
+            "expected integer literal",
+            "Expected a LitInt before `,`",
+            "expected integer literal",
+        ]);
+
+        let lenient = |input: &str| PunctSet::<LitInt>::parse_lenient.parse_str(input).unwrap();
+        let (set, errors) = lenient(r#"1, x, 3, , "s", 6,"#);
+        assert_eq!(set.to_token_stream().to_string(), "1 , 3 , 6 ,");
+        assert_eq!(errors.unwrap().into_iter().count(), 3);
+        let (set, errors) = lenient("1, 2");
+        assert_eq!(set.len(), 2);
+        assert!(errors.is_none());
+    }
+
+    #[test]
+    fn delimiters() {
+        fn split<D: Delimiter + ToTokens + 'static>(input: &str) -> Vec<String> {
+            let set: PunctSet<TokenStream2, D> = syn::parse_str(input).unwrap();
+            set.vals.iter().map(|v| v.to_string()).collect()
+        }
+
+        assert_eq!(split::<Token![=>]>("a => b == c => d = e"), ["a", "b == c", "d = e"]);
+        assert_eq!(split::<Token![=]>("a == b = c"), ["a == b", "c"]);
+        assert_eq!(split::<Token![::]>("a::b::c"), ["a", "b", "c"]);
+        assert_eq!(split::<Token![,]>("a,&b, Vec::<u8>::new(),c").len(), 4);
+        assert_eq!(split::<kw::and>("a and b.c() and f(x, y)"), ["a", "b . c ()", "f (x , y)"]);
+
+        let set: PunctSet<TokenStream2, Alt<Token![,], Token![;]>> = syn::parse_str("a, b; c, d;").unwrap();
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.to_token_stream().to_string(), "a , b ; c , d ;");
+        assert_eq!(split::<Alt<Token![=], Alt<Token![=>], Token![,]>>>("a = b => c, d").len(), 4);
+
+        let err = syn::parse_str::<PunctSet<TokenStream2, Alt<Token![,], kw::or>>>("a or , b").err().unwrap();
+        assert!(err.to_string().contains("`,` or `or`"), "{}", err);
+    }
+
+    #[test]
+    fn lines() {
+        let parse_lines = PunctSet::<Define>::parse_lines;
+        let set = parse_lines.parse_str("a: HashMap<K,\n    V>\nb: { x: 1,\n y: 2 },\nc: 3").unwrap();
+        assert_eq!(set.len(), 3);
+        assert_eq!(
+            set.to_token_stream().to_string(),
+            "a : HashMap < K , V > , b : { x : 1 , y : 2 } , c : 3"
+        );
+        let err = parse_lines.parse_str("a: 1, b: 2\nc: 3").err().unwrap();
+        assert_eq!(err.to_string(), "Expected a new line after `,`");
+
+        let set = parse_lines.parse2(quote!(a: 1)).unwrap();
+        assert_eq!(set.to_token_stream().to_string(), "a : 1");
+        assert_eq!(parse_lines.parse2(quote!(a: 1,)).unwrap().len(), 1);
+        let err = PunctSet::<Ident>::parse_lines.parse2(quote!(a b)).err().unwrap();
+        assert!(err.to_string().contains("no source locations"), "{}", err);
     }
 }
+
@@ -0,0 +1,295 @@
+use proc_macro2::Span;
+
+use crate::define::{Define, DefineSeparators, DefineValue, Defines};
+use crate::parse_utils::push_error;
+use crate::suggest::did_you_mean_suffix;
+use crate::TokenStream2;
+
+/// The kind of value a key of a `DefinesSchema` expects.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValueKind {
+    Any,
+    Lit,
+    Str,
+    Int,
+    Float,
+    Bool,
+    Path,
+    Ident,
+    Expr,
+    Type,
+    Block,
+    Defines,
+    List,
+}
+
+impl ValueKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ValueKind::Any => "any value",
+            ValueKind::Lit => "a literal",
+            ValueKind::Str => "a string literal",
+            ValueKind::Int => "an integer literal",
+            ValueKind::Float => "a float literal",
+            ValueKind::Bool => "a boolean literal",
+            ValueKind::Path => "a path",
+            ValueKind::Ident => "an ident",
+            ValueKind::Expr => "an expression",
+            ValueKind::Type => "a type",
+            ValueKind::Block => "a block",
+            ValueKind::Defines => "a block of defines",
+            ValueKind::List => "a list",
+        }
+    }
+
+    /// Whether the value can be read as this kind, i.e whether the matching
+    /// accessor of `DefineValue` succeeds.
+    pub fn matches<S: DefineSeparators>(&self, value: &DefineValue<S>) -> bool {
+        match self {
+            ValueKind::Any => true,
+            ValueKind::Lit => value.as_lit().is_ok(),
+            ValueKind::Str => value.as_lit_str().is_ok(),
+            ValueKind::Int => value.as_int::<i128>().is_ok() || value.as_int::<u128>().is_ok(),
+            ValueKind::Float => value.as_float::<f64>().is_ok(),
+            ValueKind::Bool => value.as_bool().is_ok(),
+            ValueKind::Path => value.as_path().is_ok(),
+            ValueKind::Ident => value.as_ident().is_ok(),
+            ValueKind::Expr => value.as_expr().is_ok(),
+            ValueKind::Type => value.as_type().is_ok(),
+            ValueKind::Block => value.as_block().is_ok(),
+            ValueKind::Defines => value.as_defines().is_ok(),
+            ValueKind::List => value.as_list().is_ok(),
+        }
+    }
+}
+
+/// The description of a single key of a `DefinesSchema`.
+#[derive(Clone)]
+pub struct KeySpec {
+    pub name:     String,
+    pub kind:     ValueKind,
+    pub required: bool,
+    pub default:  Option<TokenStream2>,
+    pub doc:      String,
+}
+
+impl KeySpec {
+    pub fn required(name: &str, kind: ValueKind) -> Self {
+        KeySpec {
+            name: name.to_string(),
+            kind,
+            required: true,
+            default: None,
+            doc: String::new(),
+        }
+    }
+
+    pub fn optional(name: &str, kind: ValueKind) -> Self {
+        KeySpec {
+            required: false,
+            ..Self::required(name, kind)
+        }
+    }
+
+    /// The value used for the key when it is missing, which also makes the key
+    /// optional.
+    pub fn with_default(mut self, default: TokenStream2) -> Self {
+        self.required = false;
+        self.default = Some(default);
+        self
+    }
+
+    pub fn with_doc(mut self, doc: &str) -> Self {
+        self.doc = doc.to_string();
+        self
+    }
+
+    fn matches<S: DefineSeparators>(&self, defines: &Defines<S>, define: &Define<S>) -> bool {
+        defines.key_of(define) == defines.key_policy.normalize(&self.name)
+    }
+}
+
+/// Describes the keys a macro accepts in a `Defines` block, e.g:
+/// ```ignore
+/// let schema = DefinesSchema::new(vec![
+///     KeySpec::required("label", ValueKind::Str).with_doc("The text on the button"),
+///     KeySpec::optional("color", ValueKind::Path),
+///     KeySpec::optional("padding", ValueKind::Int).with_default(quote!(4)),
+/// ]);
+/// let defines = schema.validate(&defines)?;
+/// ```
+#[derive(Clone)]
+pub struct DefinesSchema {
+    pub keys: Vec<KeySpec>,
+}
+
+impl DefinesSchema {
+    pub fn new(keys: Vec<KeySpec>) -> Self {
+        DefinesSchema {
+            keys,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&KeySpec> {
+        self.keys.iter().find(|k| k.name == name)
+    }
+
+    /// A listing of the accepted keys, with their kinds and docs.
+    pub fn help(&self) -> String {
+        self.keys
+            .iter()
+            .map(|k| {
+                let mut line = format!(
+                    "`{}` ({}, {})",
+                    k.name,
+                    if k.required { "required" } else { "optional" },
+                    k.kind.describe()
+                );
+                if !k.doc.is_empty() {
+                    line.push_str(": ");
+                    line.push_str(&k.doc);
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Validates the block against this schema, reporting every unknown,
+    /// duplicate, mistyped and missing key at once. On success, returns the
+    /// block with the defaults of the missing keys appended. Every key of the
+    /// block is marked as consumed (see `Defines::deny_unknown`).
+    pub fn validate<S: DefineSeparators>(&self, defines: &Defines<S>) -> syn::Result<Defines<S>> {
+        let mut errors: Option<syn::Error> = None;
+
+        let mut seen: Vec<&Define<S>> = Vec::new();
+        for val in &defines.vals {
+            let spec = match self.keys.iter().find(|k| k.matches(defines, val)) {
+                Some(spec) => spec,
+                None => {
+                    let name = val.ident.to_string();
+                    let suggestion =
+                        did_you_mean_suffix(&name, self.keys.iter().map(|k| k.name.as_str()));
+                    push_error(
+                        &mut errors,
+                        syn::Error::new(
+                            val.ident.span(),
+                            format!("Unknown key `{}`{}", name, suggestion),
+                        ),
+                    );
+                    continue;
+                },
+            };
+            if seen.iter().any(|s| defines.key_of(s) == defines.key_of(val)) {
+                push_error(
+                    &mut errors,
+                    syn::Error::new(val.ident.span(), format!("Duplicate key `{}`", val.ident)),
+                );
+                continue;
+            }
+            seen.push(val);
+            if !spec.kind.matches(&val.value) {
+                push_error(
+                    &mut errors,
+                    syn::Error::new_spanned(
+                        &val.value,
+                        format!(
+                            "Expected {} for `{}`, but found {}",
+                            spec.kind.describe(),
+                            spec.name,
+                            val.value.describe()
+                        ),
+                    ),
+                );
+            }
+        }
+
+        let mut result = defines.clone();
+        for spec in &self.keys {
+            if defines.vals.iter().any(|v| spec.matches(defines, v)) {
+                continue;
+            }
+            if let Some(default) = &spec.default {
+                match DefineValue::<S>::classify(default.clone()) {
+                    Ok(value) => {
+                        let ident = proc_macro2::Ident::new(&spec.name, Span::call_site());
+                        result.push(Define::new(ident, value));
+                    },
+                    Err(e) => push_error(&mut errors, e),
+                }
+            } else if spec.required {
+                push_error(
+                    &mut errors,
+                    syn::Error::new(
+                        defines.span(),
+                        format!("Missing required key `{}`", spec.name),
+                    ),
+                );
+            }
+        }
+
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        for val in defines.vals.iter().chain(&result.vals) {
+            val.mark_used();
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::{quote, ToTokens};
+
+    use super::{DefinesSchema, KeySpec, ValueKind};
+    use crate::define::Defines;
+
+    fn schema() -> DefinesSchema {
+        DefinesSchema::new(vec![
+            KeySpec::required("label", ValueKind::Str).with_doc("The text"),
+            KeySpec::optional("color", ValueKind::Path),
+            KeySpec::optional("padding", ValueKind::Int).with_default(quote!(4)),
+            KeySpec::required("size", ValueKind::Int),
+        ])
+    }
+
+    #[test]
+    fn validate() {
+        let defines: Defines = syn::parse_str(r#"label: "x", size: -3"#).unwrap();
+        let validated = schema().validate(&defines).unwrap();
+        assert_eq!(
+            validated.to_token_stream().to_string(),
+            quote!(label: "x", size: -3, padding: 4).to_string()
+        );
+        validated.deny_unknown().unwrap();
+        defines.deny_unknown().unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        let defines: Defines = syn::parse_str("label: 4, colr: Red, color: Blue, color: Red").unwrap();
+        let errors: Vec<String> = schema()
+            .validate(&defines)
+            .err()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(errors, [
+            "Expected a string literal for `label`, but found an integer literal",
+            "Unknown key `colr`, did you mean `color`?",
+            "Duplicate key `color`",
+            "Missing required key `size`",
+        ]);
+    }
+
+    #[test]
+    fn help() {
+        assert_eq!(
+            schema().help(),
+            "`label` (required, a string literal): The text\n`color` (optional, a path)\n\
+             `padding` (optional, an integer literal)\n`size` (required, an integer literal)"
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt::{Display, Formatter};
+
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::{format_ident, quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::{braced, token, LitStr, Token, Visibility};
+
+use crate::define::{to_snake_case, DefineSeparators, Defines};
+use crate::parse_utils::push_error;
+use crate::punct_set::PunctSet;
+use crate::TokenStream2;
+
+/// An enum whose variants are each identified by a string, e.g:
+/// ```text
+/// Mode { fast: "f", slow: "s", auto: "a" }
+/// pub Mode { fast | slow | auto }
+/// ```
+/// In the first form the strings are given explicitly, in the second they are
+/// the variant names in the chosen `Casing`. The variants themselves are always
+/// named in PascalCase (`Fast`, `Slow`, `Auto`).
+///
+/// `as_enum` generates the enum along with `as_str()`, an `ALL` array, and the
+/// `Display`, `FromStr` and `TryFrom<&str>` impls.
+#[derive(Clone)]
+pub struct StringEnum {
+    pub vis:         Visibility,
+    pub name:        Ident,
+    pub brace_token: token::Brace,
+    pub variants:    Vec<StringEnumVariant>,
+    pub casing:      Casing,
+}
+
+#[derive(Clone)]
+pub struct StringEnumVariant {
+    /// The key as written, e.g `fast`.
+    pub key:   Ident,
+    /// The explicit string of the variant, if any.
+    pub value: Option<LitStr>,
+}
+
+impl StringEnumVariant {
+    /// e.g `Fast` for `fast` and `BackgroundColor` for `background_color`.
+    pub fn ident(&self) -> Ident {
+        let mut ident = format_ident!("{}", Casing::Pascal.apply(&self.key.to_string()));
+        ident.set_span(self.key.span());
+        ident
+    }
+
+    /// The explicit string, or the key in the given casing.
+    pub fn as_str(&self, casing: Casing) -> String {
+        match &self.value {
+            Some(value) => value.value(),
+            None => casing.apply(&self.key.to_string()),
+        }
+    }
+}
+
+/// The casing of the strings of a `StringEnum` derived from the variant
+/// names.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Casing {
+    /// `backgroundColor` stays `backgroundColor`.
+    #[default]
+    AsWritten,
+    /// `background_color`
+    Snake,
+    /// `BACKGROUND_COLOR`
+    ScreamingSnake,
+    /// `background-color`
+    Kebab,
+    /// `backgroundColor`
+    Camel,
+    /// `BackgroundColor`
+    Pascal,
+    /// `backgroundcolor`
+    Lower,
+    /// `BACKGROUNDCOLOR`
+    Upper,
+}
+
+impl Casing {
+    pub fn apply(&self, name: &str) -> String {
+        let name = name.strip_prefix("r#").unwrap_or(name);
+        let snake = to_snake_case(name);
+        let words = snake.split('_').filter(|w| !w.is_empty());
+        let capitalize = |w: &str| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        };
+        match self {
+            Casing::AsWritten => name.to_string(),
+            Casing::Snake => words.collect::<Vec<_>>().join("_"),
+            Casing::ScreamingSnake => words.collect::<Vec<_>>().join("_").to_uppercase(),
+            Casing::Kebab => words.collect::<Vec<_>>().join("-"),
+            Casing::Camel => words
+                .enumerate()
+                .map(|(idx, w)| if idx == 0 { w.to_string() } else { capitalize(w) })
+                .collect(),
+            Casing::Pascal => words.map(capitalize).collect(),
+            Casing::Lower => name.to_lowercase(),
+            Casing::Upper => name.to_uppercase(),
+        }
+    }
+}
+
+impl StringEnum {
+    /// An enum with a variant for every define, whose value must be a string
+    /// literal.
+    pub fn from_defines<S: DefineSeparators>(name: Ident, defines: &Defines<S>) -> syn::Result<Self> {
+        let mut errors: Option<syn::Error> = None;
+        let mut variants = Vec::with_capacity(defines.vals.len());
+        for val in &defines.vals {
+            match val.value.as_lit_str() {
+                Ok(value) => variants.push(StringEnumVariant {
+                    key:   val.ident.clone(),
+                    value: Some(value.clone()),
+                }),
+                Err(e) => push_error(&mut errors, e),
+            }
+        }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        Ok(StringEnum {
+            vis: Visibility::Inherited,
+            name,
+            brace_token: token::Brace::default(),
+            variants,
+            casing: Casing::default(),
+        })
+    }
+
+    /// An enum with a variant for every ident, whose strings are the idents in
+    /// the given casing.
+    pub fn from_idents(name: Ident, idents: &PunctSet<Ident, Token![|]>, casing: Casing) -> Self {
+        StringEnum {
+            vis: Visibility::Inherited,
+            name,
+            brace_token: token::Brace::default(),
+            variants: idents
+                .vals
+                .iter()
+                .map(|key| StringEnumVariant {
+                    key:   key.clone(),
+                    value: None,
+                })
+                .collect(),
+            casing,
+        }
+    }
+
+    pub fn with_casing(mut self, casing: Casing) -> Self {
+        self.casing = casing;
+        self
+    }
+
+    pub fn with_vis(mut self, vis: Visibility) -> Self {
+        self.vis = vis;
+        self
+    }
+
+    /// Errors on every variant whose name or string is already used by an
+    /// earlier one, and on an enum without variants.
+    pub fn validate(&self) -> syn::Result<()> {
+        let mut errors: Option<syn::Error> = None;
+        if self.variants.is_empty() {
+            push_error(
+                &mut errors,
+                syn::Error::new(self.name.span(), format!("`{}` has no variants", self.name)),
+            );
+        }
+        for (idx, variant) in self.variants.iter().enumerate() {
+            let earlier = &self.variants[..idx];
+            let ident = variant.ident();
+            let value = variant.as_str(self.casing);
+            if let Some(first) = earlier.iter().find(|v| v.ident() == ident) {
+                push_error(
+                    &mut errors,
+                    syn::Error::new(
+                        variant.key.span(),
+                        format!("Variant `{}` of `{}` is the same as `{}`", ident, self.name, first.key),
+                    ),
+                );
+            } else if let Some(first) = earlier.iter().find(|v| v.as_str(self.casing) == value) {
+                push_error(
+                    &mut errors,
+                    syn::Error::new(
+                        variant.key.span(),
+                        format!("The string \"{}\" is already used by `{}`", value, first.key),
+                    ),
+                );
+            }
+        }
+        match errors {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// The enum, e.g for `Mode { fast: "f", slow: "s" }`:
+    /// ```text
+    /// #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    /// enum Mode { Fast, Slow }
+    /// impl Mode {
+    ///     pub const ALL: [Mode; 2] = [Mode::Fast, Mode::Slow];
+    ///     pub fn as_str(&self) -> &'static str { .. }
+    /// }
+    /// impl Display for Mode { .. }
+    /// impl FromStr for Mode { type Err = String; .. }
+    /// impl TryFrom<&str> for Mode { type Error = String; .. }
+    /// ```
+    pub fn as_enum(&self) -> syn::Result<TokenStream2> {
+        self.validate()?;
+        let vis = &self.vis;
+        let name = &self.name;
+        let idents: Vec<Ident> = self.variants.iter().map(|v| v.ident()).collect();
+        let strs: Vec<String> = self.variants.iter().map(|v| v.as_str(self.casing)).collect();
+        let len = Literal::usize_unsuffixed(idents.len());
+        let expected = strs
+            .iter()
+            .map(|s| format!("`{}`", s.replace('{', "{{").replace('}', "}}")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let unknown = LitStr::new(
+            &format!("Unknown {} `{{}}`, expected one of {}", name, expected),
+            Span::call_site(),
+        );
+        Ok(quote! {
+            #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+            #vis enum #name {
+                #(#idents),*
+            }
+
+            impl #name {
+                pub const ALL: [#name; #len] = [#(#name::#idents),*];
+
+                pub fn as_str(&self) -> &'static str {
+                    match self {
+                        #(#name::#idents => #strs,)*
+                    }
+                }
+            }
+
+            impl ::core::fmt::Display for #name {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    f.write_str(self.as_str())
+                }
+            }
+
+            impl ::core::str::FromStr for #name {
+                type Err = ::std::string::String;
+
+                fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {
+                    match s {
+                        #(#strs => ::core::result::Result::Ok(#name::#idents),)*
+                        _ => ::core::result::Result::Err(::std::format!(#unknown, s)),
+                    }
+                }
+            }
+
+            impl ::core::convert::TryFrom<&str> for #name {
+                type Error = ::std::string::String;
+
+                fn try_from(s: &str) -> ::core::result::Result<Self, Self::Error> {
+                    ::core::str::FromStr::from_str(s)
+                }
+            }
+        })
+    }
+}
+
+impl Display for StringEnum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_token_stream().to_string())
+    }
+}
+
+impl ToTokens for StringEnum {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        self.vis.to_tokens(tokens);
+        self.name.to_tokens(tokens);
+        let all_explicit = self.variants.iter().all(|v| v.value.is_some());
+        self.brace_token.surround(tokens, |inner| {
+            for (idx, variant) in self.variants.iter().enumerate() {
+                if idx > 0 {
+                    if all_explicit {
+                        <Token![,]>::default().to_tokens(inner);
+                    } else {
+                        <Token![|]>::default().to_tokens(inner);
+                    }
+                }
+                variant.key.to_tokens(inner);
+                if let (true, Some(value)) = (all_explicit, &variant.value) {
+                    <Token![:]>::default().to_tokens(inner);
+                    value.to_tokens(inner);
+                }
+            }
+        });
+    }
+}
+
+impl Parse for StringEnum {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let vis: Visibility = input.parse()?;
+        let name: Ident = input.parse()?;
+        let content;
+        let brace_token = braced!(content in input);
+        let result = if content.peek(syn::Ident) && content.peek2(Token![:]) {
+            let defines: Defines = content.parse()?;
+            StringEnum::from_defines(name, &defines)?
+        } else {
+            let idents: PunctSet<Ident, Token![|]> = content.parse()?;
+            StringEnum::from_idents(name, &idents, Casing::default())
+        };
+        Ok(StringEnum {
+            vis,
+            brace_token,
+            ..result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Casing, StringEnum};
+
+    #[test]
+    fn as_enum() {
+        let string_enum: StringEnum =
+            syn::parse_str(r#"pub Mode { fast: "f", slow: "s", auto_mode: "a" }"#).unwrap();
+        assert_eq!(string_enum.to_string(), r#"pub Mode { fast : "f" , slow : "s" , auto_mode : "a" }"#);
+        let generated = string_enum.as_enum().unwrap();
+        let file = syn::parse2::<syn::File>(generated.clone()).unwrap();
+        assert_eq!(file.items.len(), 5);
+        let generated = generated.to_string();
+        assert!(generated.contains("pub enum Mode { Fast , Slow , AutoMode }"), "{}", generated);
+        assert!(generated.contains(r#"Mode :: AutoMode => "a""#), "{}", generated);
+
+        let empty: StringEnum = syn::parse_str("Empty {}").unwrap();
+        assert_eq!(empty.as_enum().err().unwrap().to_string(), "`Empty` has no variants");
+    }
+
+    #[test]
+    fn casings() {
+        let string_enum: StringEnum = syn::parse_str("Color { darkRed | light_blue | Green }").unwrap();
+        let strings = |casing: Casing| -> Vec<String> {
+            string_enum.variants.iter().map(|v| v.as_str(casing)).collect()
+        };
+        assert_eq!(strings(Casing::AsWritten), ["darkRed", "light_blue", "Green"]);
+        assert_eq!(strings(Casing::Snake), ["dark_red", "light_blue", "green"]);
+        assert_eq!(strings(Casing::Kebab), ["dark-red", "light-blue", "green"]);
+        assert_eq!(strings(Casing::Camel), ["darkRed", "lightBlue", "green"]);
+        assert_eq!(strings(Casing::Pascal), ["DarkRed", "LightBlue", "Green"]);
+        assert_eq!(strings(Casing::ScreamingSnake), ["DARK_RED", "LIGHT_BLUE", "GREEN"]);
+        assert_eq!(strings(Casing::Lower), ["darkred", "light_blue", "green"]);
+        assert_eq!(strings(Casing::Upper), ["DARKRED", "LIGHT_BLUE", "GREEN"]);
+        let idents: Vec<String> = string_enum.variants.iter().map(|v| v.ident().to_string()).collect();
+        assert_eq!(idents, ["DarkRed", "LightBlue", "Green"]);
+    }
+
+    #[test]
+    fn errors() {
+        let err = syn::parse_str::<StringEnum>(r#"Bad { a: "x", c: 1 }"#).err().unwrap();
+        assert_eq!(err.to_string(), "Expected a string literal, but found an integer literal");
+
+        let string_enum: StringEnum = syn::parse_str(r#"Bad { a: "x", A: "y", b: "x" }"#).unwrap();
+        let errors: Vec<String> =
+            string_enum.validate().err().unwrap().into_iter().map(|e| e.to_string()).collect();
+        assert_eq!(errors, [
+            "Variant `A` of `Bad` is the same as `a`",
+            r#"The string "x" is already used by `a`"#,
+        ]);
+    }
+}
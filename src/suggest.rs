@@ -0,0 +1,39 @@
+/// The Levenshtein distance between `a` and `b`, i.e the amount of single
+/// character insertions, deletions and substitutions to get from one to the
+/// other.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr: Vec<usize> = vec![0; b.len() + 1];
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j + 1] + 1).min(curr[j] + 1).min(prev[j] + cost);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+/// Finds the candidate closest to `name`, as long as it is close enough to
+/// likely be what was meant (i.e a typo), for "did you mean" suggestions.
+pub fn did_you_mean<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/// Formats the `did_you_mean` suggestion (if any) to be appended to an error
+/// message, e.g "unknown key `colr`" + ", did you mean `color`?".
+pub fn did_you_mean_suffix<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I) -> String {
+    match did_you_mean(name, candidates) {
+        Some(s) => format!(", did you mean `{}`?", s),
+        None => String::new(),
+    }
+}
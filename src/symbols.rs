@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+
+use debug_helpers::debug_file;
+use proc_macro2::Ident;
+use syn::{Expr, Pat};
+
+use crate::declaration::Declarations;
+use crate::node::{ElseBranch, Node, NodeDeclaration, Nodes};
+use crate::param::{Arg, Args};
+use crate::parse_utils::push_error;
+use crate::suggest::did_you_mean_suffix;
+
+/// A named declaration, e.g `save_btn` in:
+/// ```text
+/// save_btn: Button(label: "Save")
+/// ```
+pub struct Symbol<'a> {
+    pub name:        &'a Ident,
+    pub declaration: &'a NodeDeclaration,
+    /// The names of other declarations referenced in the args of this one,
+    /// e.g `save_btn` in `Form(focus: save_btn)`.
+    pub references:  Vec<&'a Ident>,
+    /// The scope the symbol was declared in, see `SymbolTable`.
+    scope:           usize,
+}
+
+/// All the named declarations of a declaration tree, including the ones nested
+/// in bodies and in `if`/`for`/`match` branches.
+///
+/// Every branch of an `if` or a `match`, and every `for` body, is a scope of
+/// its own, so that a name may be declared again in a branch that excludes the
+/// other, e.g:
+/// ```text
+/// if compact { btn: Button(size: small) } else { btn: Button(size: large) }
+/// ```
+/// References resolve to the closest declaration in the enclosing scopes,
+/// while the names declared inside a branch are not visible outside of it.
+/// Declaration bodies share the scope of their declaration.
+pub struct SymbolTable<'a> {
+    pub symbols:  Vec<Symbol<'a>>,
+    scopes:       Vec<Scope<'a>>,
+    /// For every symbol, the symbols its references resolved to.
+    dependencies: Vec<Vec<usize>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Builds the table for a whole document. `is_reference` picks which plain
+    /// idents in args refer to other declarations (e.g by the naming
+    /// convention of the macro), so that runtime variables and types are left
+    /// alone. The idents bound by the patterns of enclosing `for` and `match`
+    /// nodes are never references.
+    pub fn build(
+        declarations: &'a Declarations<Nodes, Args>,
+        is_reference: impl Fn(&Ident) -> bool,
+    ) -> syn::Result<Self> {
+        let mut collector = Collector::new();
+        for val in &declarations.vals {
+            collector.visit_declaration(val, 0, &is_reference);
+        }
+        collector.finish()
+    }
+
+    /// Builds the table for a bare tree of nodes, see `build`.
+    pub fn from_nodes(nodes: &'a Nodes, is_reference: impl Fn(&Ident) -> bool) -> syn::Result<Self> {
+        let mut collector = Collector::new();
+        collector.visit_nodes(nodes, 0, &is_reference);
+        collector.finish()
+    }
+
+    /// Looks up a symbol declared outside of any branch or loop.
+    pub fn get(&self, name: &str) -> Option<&Symbol<'a>> {
+        self.scopes[0].names.get(name).map(|idx| &self.symbols[*idx])
+    }
+
+    /// Orders the symbols so that every symbol comes after the symbols it
+    /// references, keeping the declaration order otherwise. Cyclic references
+    /// are reported as errors.
+    pub fn construction_order(&self) -> syn::Result<Vec<&Symbol<'a>>> {
+        let mut order: Vec<usize> = Vec::with_capacity(self.symbols.len());
+        let mut state: Vec<VisitState> = vec![VisitState::Unvisited; self.symbols.len()];
+        let mut errors: Option<syn::Error> = None;
+        for idx in 0..self.symbols.len() {
+            let mut stack = Vec::new();
+            self.visit_order(idx, &mut state, &mut stack, &mut order, &mut errors);
+        }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        Ok(order.into_iter().map(|idx| &self.symbols[idx]).collect())
+    }
+
+    fn visit_order(
+        &self,
+        idx: usize,
+        state: &mut Vec<VisitState>,
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+        errors: &mut Option<syn::Error>,
+    ) {
+        match state[idx] {
+            VisitState::Done => return,
+            VisitState::InProgress => {
+                let start = stack.iter().position(|i| *i == idx).unwrap_or(0);
+                let chain = stack[start..]
+                    .iter()
+                    .chain(Some(&idx))
+                    .map(|i| self.symbols[*i].name.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                push_error(
+                    errors,
+                    syn::Error::new(
+                        self.symbols[idx].name.span(),
+                        format!("Cyclic references: {}", chain),
+                    ),
+                );
+                return;
+            },
+            VisitState::Unvisited => {},
+        }
+        state[idx] = VisitState::InProgress;
+        stack.push(idx);
+        for dep in &self.dependencies[idx] {
+            self.visit_order(*dep, state, stack, order, errors);
+        }
+        stack.pop();
+        state[idx] = VisitState::Done;
+        order.push(idx);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VisitState {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+/// The names declared in a branch, a loop body or the document itself, along
+/// with the idents bound by the pattern that opened it (if any).
+struct Scope<'a> {
+    parent:   Option<usize>,
+    bindings: Vec<&'a Ident>,
+    names:    HashMap<String, usize>,
+}
+
+/// What a reference resolves to.
+enum Resolved {
+    Symbol(usize),
+    /// A variable bound by a `for` or `match` pattern.
+    Binding,
+    Undefined,
+}
+
+struct Collector<'a> {
+    symbols:    Vec<Symbol<'a>>,
+    scopes:     Vec<Scope<'a>>,
+    /// References made by unnamed declarations, which still need to resolve,
+    /// along with the scope they were made in.
+    references: Vec<(&'a Ident, usize)>,
+    errors:     Option<syn::Error>,
+}
+
+impl<'a> Collector<'a> {
+    fn new() -> Self {
+        Collector {
+            symbols:    Vec::new(),
+            scopes:     vec![Scope {
+                parent:   None,
+                bindings: Vec::new(),
+                names:    HashMap::new(),
+            }],
+            references: Vec::new(),
+            errors:     None,
+        }
+    }
+
+    fn open_scope(&mut self, parent: usize, pat: Option<&'a Pat>) -> usize {
+        let mut bindings = Vec::new();
+        if let Some(pat) = pat {
+            pat_bindings(pat, &mut bindings);
+        }
+        self.scopes.push(Scope {
+            parent: Some(parent),
+            bindings,
+            names: HashMap::new(),
+        });
+        self.scopes.len() - 1
+    }
+
+    fn visit_declaration(
+        &mut self,
+        val: &'a NodeDeclaration,
+        scope: usize,
+        is_reference: &impl Fn(&Ident) -> bool,
+    ) {
+        let references = match &val.params {
+            Some(args) => args_references(args, is_reference),
+            None => Vec::new(),
+        };
+        match &val.name {
+            Some(name) => {
+                let key = name.to_string();
+                match self.scopes[scope].names.get(&key) {
+                    Some(first) => {
+                        let first = self.symbols[*first].name;
+                        let mut e = syn::Error::new(
+                            name.span(),
+                            format!("Duplicate declaration name `{}`", name),
+                        );
+                        e.combine(syn::Error::new(
+                            first.span(),
+                            format!("`{}` is first declared here", first),
+                        ));
+                        self.error(e);
+                    },
+                    None => {
+                        self.scopes[scope].names.insert(key, self.symbols.len());
+                    },
+                }
+                self.symbols.push(Symbol {
+                    name,
+                    declaration: val,
+                    references,
+                    scope,
+                });
+            },
+            None => self.references.extend(references.into_iter().map(|r| (r, scope))),
+        }
+        if let Some((_, body)) = &val.body {
+            self.visit_nodes(body, scope, is_reference);
+        }
+    }
+
+    fn visit_nodes(&mut self, nodes: &'a Nodes, scope: usize, is_reference: &impl Fn(&Ident) -> bool) {
+        for node in &nodes.vals {
+            match node {
+                Node::Declaration(d) => self.visit_declaration(d, scope, is_reference),
+                Node::If(n) => {
+                    let branch = self.open_scope(scope, None);
+                    self.visit_nodes(&n.then_branch, branch, is_reference);
+                    let mut else_branch = n.else_branch.as_ref().map(|(_, b)| b.as_ref());
+                    while let Some(branch) = else_branch {
+                        let branch_scope = self.open_scope(scope, None);
+                        match branch {
+                            ElseBranch::If(n) => {
+                                self.visit_nodes(&n.then_branch, branch_scope, is_reference);
+                                else_branch = n.else_branch.as_ref().map(|(_, b)| b.as_ref());
+                            },
+                            ElseBranch::Nodes(_, nodes) => {
+                                self.visit_nodes(nodes, branch_scope, is_reference);
+                                else_branch = None;
+                            },
+                        }
+                    }
+                },
+                Node::For(n) => {
+                    let body = self.open_scope(scope, Some(&n.pat));
+                    self.visit_nodes(&n.body, body, is_reference);
+                },
+                Node::Match(n) => {
+                    for arm in &n.arms {
+                        let arm_scope = self.open_scope(scope, Some(&arm.pat));
+                        self.visit_nodes(&arm.body, arm_scope, is_reference);
+                    }
+                },
+            }
+        }
+    }
+
+    fn resolve(&self, reference: &Ident, mut scope: usize) -> Resolved {
+        let name = reference.to_string();
+        loop {
+            let current = &self.scopes[scope];
+            if let Some(idx) = current.names.get(&name) {
+                return Resolved::Symbol(*idx);
+            }
+            if current.bindings.iter().any(|b| **b == name) {
+                return Resolved::Binding;
+            }
+            match current.parent {
+                Some(parent) => scope = parent,
+                None => return Resolved::Undefined,
+            }
+        }
+    }
+
+    /// The names visible from `scope`, for suggestions.
+    fn visible_names(&self, mut scope: usize) -> Vec<&str> {
+        let mut names = Vec::new();
+        loop {
+            let current = &self.scopes[scope];
+            names.extend(current.names.keys().map(|k| k.as_str()));
+            match current.parent {
+                Some(parent) => scope = parent,
+                None => return names,
+            }
+        }
+    }
+
+    fn error(&mut self, e: syn::Error) {
+        push_error(&mut self.errors, e);
+    }
+
+    fn finish(mut self) -> syn::Result<SymbolTable<'a>> {
+        let mut undefined: Vec<syn::Error> = Vec::new();
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); self.symbols.len()];
+        let all_references = self
+            .symbols
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, s)| s.references.iter().map(move |r| (*r, s.scope, Some(idx))))
+            .chain(self.references.iter().map(|(r, scope)| (*r, *scope, None)));
+        for (reference, scope, symbol) in all_references {
+            match self.resolve(reference, scope) {
+                Resolved::Symbol(dep) => {
+                    if let Some(symbol) = symbol {
+                        dependencies[symbol].push(dep);
+                    }
+                },
+                Resolved::Binding => {},
+                Resolved::Undefined => {
+                    let name = reference.to_string();
+                    let suggestion = did_you_mean_suffix(&name, self.visible_names(scope));
+                    undefined.push(syn::Error::new(
+                        reference.span(),
+                        format!("Undefined reference `{}`{}", name, suggestion),
+                    ));
+                },
+            }
+        }
+        for e in undefined {
+            self.error(e);
+        }
+        if let Some(e) = self.errors {
+            return Err(e);
+        }
+        debug_file!(!"Built a symbol table with {} symbols", self.symbols.len());
+        Ok(SymbolTable {
+            symbols: self.symbols,
+            scopes: self.scopes,
+            dependencies,
+        })
+    }
+}
+
+/// Every ident bound by a pattern, e.g `idx` and `item` in `(idx, item)`.
+fn pat_bindings<'a>(pat: &'a Pat, result: &mut Vec<&'a Ident>) {
+    match pat {
+        Pat::Ident(p) => {
+            result.push(&p.ident);
+            if let Some((_, sub)) = &p.subpat {
+                pat_bindings(sub, result);
+            }
+        },
+        Pat::Or(p) => p.cases.iter().for_each(|p| pat_bindings(p, result)),
+        Pat::Paren(p) => pat_bindings(&p.pat, result),
+        Pat::Reference(p) => pat_bindings(&p.pat, result),
+        Pat::Slice(p) => p.elems.iter().for_each(|p| pat_bindings(p, result)),
+        Pat::Struct(p) => p.fields.iter().for_each(|f| pat_bindings(&f.pat, result)),
+        Pat::Tuple(p) => p.elems.iter().for_each(|p| pat_bindings(p, result)),
+        Pat::TupleStruct(p) => p.elems.iter().for_each(|p| pat_bindings(p, result)),
+        Pat::Type(p) => pat_bindings(&p.pat, result),
+        _ => {},
+    }
+}
+
+fn args_references<'a>(args: &'a Args, is_reference: &impl Fn(&Ident) -> bool) -> Vec<&'a Ident> {
+    args.vals
+        .iter()
+        .filter_map(arg_reference)
+        .filter(|ident| is_reference(ident))
+        .collect()
+}
+
+/// An arg references another declaration when its value is just a plain
+/// ident, e.g `focus: save_btn`.
+fn arg_reference(arg: &Arg) -> Option<&Ident> {
+    match &arg.value {
+        Expr::Path(expr) if expr.qself.is_none() => expr.path.get_ident(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proc_macro2::Ident;
+
+    use super::SymbolTable;
+    use crate::declaration::Declarations;
+    use crate::node::Nodes;
+    use crate::param::Args;
+
+    fn is_reference(ident: &Ident) -> bool {
+        ident.to_string().ends_with("btn") || ident == "form"
+    }
+
+    fn messages(result: syn::Result<SymbolTable>) -> Vec<String> {
+        match result {
+            Ok(_) => panic!("Expected the symbols to fail"),
+            Err(e) => e.into_iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    fn messages_of(nodes: &Nodes) -> Vec<String> {
+        messages(SymbolTable::from_nodes(nodes, is_reference))
+    }
+
+    #[test]
+    fn names() {
+        let document: Declarations<Nodes, Args> = syn::parse_str(
+            "form: Form(focus: save_btn) { if x { save_btn: Button() } else { Text(sav_btn) } }; \
+             cancel: Button(next: form); cancel: Text",
+        )
+        .unwrap();
+        let errors = messages(SymbolTable::build(&document, is_reference));
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert!(errors[0].contains("Duplicate declaration name `cancel`"));
+        assert!(errors[2].contains("Undefined reference `save_btn`"));
+        assert_eq!(errors[3], "Undefined reference `sav_btn`");
+
+        let document: Declarations<Nodes, Args> =
+            syn::parse_str("save_btn: Button(); Form(focus: sav_btn)").unwrap();
+        let undefined = messages(SymbolTable::build(&document, is_reference));
+        assert_eq!(undefined.len(), 1);
+        assert!(undefined[0].starts_with("Undefined reference `sav_btn`"));
+        assert!(undefined[0].contains("`save_btn`"), "{}", undefined[0]);
+    }
+
+    #[test]
+    fn scopes() {
+        let nodes: Nodes = syn::parse_str(
+            "if a { btn: Button(x) } else { btn: Button(y) } \
+             for row_btn in rows { Row(row_btn) } \
+             match s { S::A(item_btn) => Text(item_btn), _ => { Text(other_btn) } }",
+        )
+        .unwrap();
+        let errors = messages(SymbolTable::from_nodes(&nodes, is_reference));
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].contains("`other_btn`"));
+
+        let nodes: Nodes = syn::parse_str("if a { btn: Button(x) } Text(btn)").unwrap();
+        assert_eq!(messages_of(&nodes).len(), 1);
+
+        let nodes: Nodes = syn::parse_str("btn: Button(x) if a { Text(btn) }").unwrap();
+        let table = SymbolTable::from_nodes(&nodes, is_reference).unwrap();
+        assert!(table.get("btn").is_some());
+    }
+
+    #[test]
+    fn construction_order() {
+        let document: Declarations<Nodes, Args> = syn::parse_str(
+            "form: Form(focus: save_btn) { save_btn: Button() }; cancel_btn: Button(next: form)",
+        )
+        .unwrap();
+        let table = SymbolTable::build(&document, is_reference).unwrap();
+        let order = table
+            .construction_order()
+            .unwrap()
+            .iter()
+            .map(|s| s.name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(order, ["save_btn", "form", "cancel_btn"]);
+
+        let document: Declarations<Nodes, Args> =
+            syn::parse_str("a_btn: A(x: b_btn); b_btn: B(x: a_btn)").unwrap();
+        let table = SymbolTable::build(&document, is_reference).unwrap();
+        let errors = table.construction_order().err().unwrap().to_string();
+        assert!(errors.contains("Cyclic references: a_btn -> b_btn -> a_btn"), "{}", errors);
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use debug_helpers::debug_file;
+use proc_macro2::{Ident, Span};
+use quote::ToTokens;
+use syn::parse::Parse;
+
+use crate::declaration::{Declaration, Declarations, Stmts};
+use crate::define::{Define, DefineSeparators, Defines};
+use crate::node::{ElseBranch, IfNode, Node, Nodes};
+use crate::param::{Arg, Args, Param, Params};
+use crate::parse_utils::push_error;
+use crate::punct_set::{Delimiter, PunctSet};
+use crate::TokenStream2;
+
+/// Params that can be merged over the params of a template, entry by entry.
+/// Implemented for `Params` and `Args` (keyed by the param name) and `Defines`
+/// (keyed by the define name).
+pub trait TemplateParams: Sized {
+    /// Merges the entries of `self` over the ones of `base`, so that entries
+    /// with the same key replace the base entry in place, and new ones are
+    /// appended. Returns the merged params along with the overridden entries.
+    fn merge_over(&self, base: &Self, template: &Ident) -> syn::Result<(Self, Vec<TemplateOverride>)>;
+}
+
+/// A param of a template that was replaced at the use site, e.g `color` in:
+/// ```text
+/// PrimaryButton = Button(color: Blue)
+/// PrimaryButton(color: Red)
+/// ```
+#[derive(Clone, Debug)]
+pub struct TemplateOverride {
+    pub template: Ident,
+    /// The key as written at the use site.
+    pub key:      Ident,
+    /// The key as written in the template.
+    pub replaced: Ident,
+}
+
+impl TemplateParams for Params {
+    fn merge_over(&self, base: &Self, template: &Ident) -> syn::Result<(Self, Vec<TemplateOverride>)> {
+        merge_punct_sets(self, base, template, |p: &Param| {
+            p.name.clone().map(|name| (name.to_string(), name))
+        })
+    }
+}
+
+impl TemplateParams for Args {
+    fn merge_over(&self, base: &Self, template: &Ident) -> syn::Result<(Self, Vec<TemplateOverride>)> {
+        merge_punct_sets(self, base, template, |a: &Arg| {
+            a.name.as_ref().map(|(name, _)| (name.to_string(), name.clone()))
+        })
+    }
+}
+
+impl<S: DefineSeparators> TemplateParams for Defines<S> {
+    fn merge_over(&self, base: &Self, template: &Ident) -> syn::Result<(Self, Vec<TemplateOverride>)> {
+        let key_policy = self.key_policy;
+        let (set, overrides) = merge_punct_sets(&self.set, &base.set, template, |d: &Define<S>| {
+            Some((key_policy.normalize(&d.key()), d.ident.clone()))
+        })?;
+        Ok((
+            Defines {
+                set,
+                key_policy,
+            },
+            overrides,
+        ))
+    }
+}
+
+/// The body of a declaration, which templates may be used inside of. Bodies
+/// that hold no declarations are kept as they are, whereas `Nodes` have every
+/// declaration of their tree passed through `apply`, including the ones in the
+/// branches of `if`, `for` and `match` nodes.
+pub trait TemplateBody<P: Parse + ToTokens + Display>: Parse + ToTokens + Clone {
+    fn apply_templates(&self, apply: &mut ApplyTemplates<'_, Self, P>) -> syn::Result<Self>;
+}
+
+/// Applies the templates to a single declaration of a `TemplateBody`.
+pub type ApplyTemplates<'a, B, P> = dyn FnMut(&Declaration<B, P>) -> syn::Result<Declaration<B, P>> + 'a;
+
+macro_rules! impl_opaque_template_body {
+    ($($ty:ty $(where $($gen:ident: $bound:path),*)?;)*) => {
+        $(
+            impl<P: Parse + ToTokens + Display $($(, $gen: $bound)*)?> TemplateBody<P> for $ty {
+                fn apply_templates(&self, _: &mut ApplyTemplates<'_, Self, P>) -> syn::Result<Self> {
+                    Ok(self.clone())
+                }
+            }
+        )*
+    };
+}
+
+impl_opaque_template_body! {
+    TokenStream2;
+    Stmts;
+    Defines<S> where S: DefineSeparators;
+}
+
+impl TemplateBody<Args> for Nodes {
+    fn apply_templates(&self, apply: &mut ApplyTemplates<'_, Self, Args>) -> syn::Result<Self> {
+        let mut errors: Option<syn::Error> = None;
+        let mut vals = Vec::with_capacity(self.vals.len());
+        for val in &self.vals {
+            match apply_templates_to_node(val, apply) {
+                Ok(val) => vals.push(val),
+                Err(e) => push_error(&mut errors, e),
+            }
+        }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        Ok(Nodes {
+            vals,
+        })
+    }
+}
+
+fn apply_templates_to_node(
+    node: &Node,
+    apply: &mut ApplyTemplates<'_, Nodes, Args>,
+) -> syn::Result<Node> {
+    Ok(match node {
+        Node::Declaration(d) => Node::Declaration(apply(d)?),
+        Node::If(n) => Node::If(apply_templates_to_if(n, apply)?),
+        Node::For(n) => {
+            let mut n = n.clone();
+            n.body = n.body.apply_templates(apply)?;
+            Node::For(n)
+        },
+        Node::Match(n) => {
+            let mut n = n.clone();
+            for arm in &mut n.arms {
+                arm.body = arm.body.apply_templates(apply)?;
+            }
+            Node::Match(n)
+        },
+    })
+}
+
+fn apply_templates_to_if(
+    node: &IfNode,
+    apply: &mut ApplyTemplates<'_, Nodes, Args>,
+) -> syn::Result<IfNode> {
+    let mut node = node.clone();
+    node.then_branch = node.then_branch.apply_templates(apply)?;
+    if let Some((_, branch)) = &mut node.else_branch {
+        **branch = match branch.as_ref() {
+            ElseBranch::If(n) => ElseBranch::If(apply_templates_to_if(n, apply)?),
+            ElseBranch::Nodes(brace, nodes) => ElseBranch::Nodes(*brace, nodes.apply_templates(apply)?),
+        };
+    }
+    Ok(node)
+}
+
+fn merge_punct_sets<
+    T: Parse + Display + ToTokens + Clone,
+    D: Delimiter + Clone + ToTokens,
+>(
+    over: &PunctSet<T, D>,
+    base: &PunctSet<T, D>,
+    template: &Ident,
+    key_of: impl Fn(&T) -> Option<(String, Ident)>,
+) -> syn::Result<(PunctSet<T, D>, Vec<TemplateOverride>)> {
+    let mut result: PunctSet<T, D> = base.clone();
+    let base_len = result.len();
+    let mut overrides = Vec::new();
+    let mut errors: Option<syn::Error> = None;
+    let mut seen: Vec<String> = Vec::new();
+    for (over_idx, val) in over.vals.iter().enumerate() {
+        let span = over.span_of(over_idx).unwrap_or_else(Span::call_site);
+        let (key, ident) = match key_of(val) {
+            Some(key) => key,
+            None if base.vals.is_empty() => {
+                result.push_spanned(val.clone(), None, span);
+                continue;
+            },
+            None => {
+                push_error(
+                    &mut errors,
+                    syn::Error::new_spanned(
+                        val,
+                        format!(
+                            "Positional params can't be merged over the params of template `{}`, \
+                             give the param a name instead",
+                            template
+                        ),
+                    ),
+                );
+                continue;
+            },
+        };
+        if seen.contains(&key) {
+            push_error(
+                &mut errors,
+                syn::Error::new(ident.span(), format!("Duplicate param `{}`", ident)),
+            );
+            continue;
+        }
+        let existing = base
+            .vals
+            .iter()
+            .position(|b| key_of(b).is_some_and(|(k, _)| k == key));
+        seen.push(key);
+        match existing {
+            Some(idx) => {
+                let (_, replaced) = key_of(&base.vals[idx]).expect("Expected a keyed base param");
+                overrides.push(TemplateOverride {
+                    template: template.clone(),
+                    key: ident,
+                    replaced,
+                });
+                result.vals[idx] = val.clone();
+                if let Some(base_span) = result.spans.get_mut(idx) {
+                    *base_span = span;
+                }
+            },
+            None => {
+                result.push_spanned(val.clone(), None, span);
+            },
+        }
+    }
+    if let Some(e) = errors {
+        return Err(e);
+    }
+    // The appended params are delimited by the delimiter of the set, and the
+    // last one ends the set the way the use did
+    if result.len() > base_len {
+        if let Some(last) = result.delims.last_mut() {
+            *last = over.trailing().cloned();
+        }
+    }
+    Ok((result, overrides))
+}
+
+/// The result of applying the templates of a `Declarations` document.
+pub struct ResolvedTemplates<
+    B: Parse + ToTokens = crate::TokenStream2,
+    P: Parse + ToTokens + Display = Params,
+> {
+    /// The document without the template definitions, and with every use of a
+    /// template replaced by the declaration it expands to.
+    pub declarations: Declarations<B, P>,
+    pub overrides:    Vec<TemplateOverride>,
+}
+
+impl<B, P> Declarations<B, P>
+where
+    B: TemplateBody<P>,
+    P: Parse + ToTokens + Display + Clone + TemplateParams,
+{
+    /// Applies the template definitions of this document (`Name = Base(..)`)
+    /// to every declaration using them (`Name(..)`). The params of the use are
+    /// merged over the params of the template, and the trails of both are
+    /// combined. Templates may be based on other templates, and may be defined
+    /// after their uses, and are also applied to the declarations nested in
+    /// the bodies of other declarations (see `TemplateBody`).
+    ///
+    /// Every conflict (duplicate templates, cyclic templates, bodies defined by
+    /// both the template and the use, or params that can't be merged) is
+    /// reported, all combined into a single error.
+    pub fn resolve_templates(&self) -> syn::Result<ResolvedTemplates<B, P>> {
+        let mut errors: Option<syn::Error> = None;
+        let mut templates: HashMap<String, &Declaration<B, P>> = HashMap::new();
+        for val in self.vals.iter().filter(|v| v.base.is_some()) {
+            let key = val.ty.to_string();
+            if let Some(existing) = templates.get(&key) {
+                let mut e = syn::Error::new(
+                    val.ty.span(),
+                    format!("Template `{}` is defined more than once", key),
+                );
+                e.combine(syn::Error::new(
+                    existing.ty.span(),
+                    format!("Template `{}` is first defined here", key),
+                ));
+                push_error(&mut errors, e);
+                continue;
+            }
+            templates.insert(key, val);
+        }
+
+        let mut declarations = Declarations::empty();
+        let mut overrides = Vec::new();
+        for ((val, sep), span) in self.vals.iter().zip(&self.seps).zip(&self.spans) {
+            if val.base.is_some() {
+                continue;
+            }
+            match apply_templates(val, &templates, &mut Vec::new(), &mut overrides) {
+                Ok(resolved) => {
+                    debug_file!(resolved, "Resolved a Declaration from a template, value below:");
+                    declarations.vals.push(resolved);
+                    declarations.seps.push(sep.clone());
+                    declarations.spans.push(*span);
+                },
+                Err(e) => push_error(&mut errors, e),
+            }
+        }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+        Ok(ResolvedTemplates {
+            declarations,
+            overrides,
+        })
+    }
+}
+
+fn apply_templates<B, P>(
+    val: &Declaration<B, P>,
+    templates: &HashMap<String, &Declaration<B, P>>,
+    stack: &mut Vec<Ident>,
+    overrides: &mut Vec<TemplateOverride>,
+) -> syn::Result<Declaration<B, P>>
+where
+    B: TemplateBody<P>,
+    P: Parse + ToTokens + Display + Clone + TemplateParams,
+{
+    let body = match &val.body {
+        Some((brace, body)) => Some((
+            *brace,
+            body.apply_templates(&mut |d| apply_templates(d, templates, &mut Vec::new(), overrides))?,
+        )),
+        None => None,
+    };
+    let template = match templates.get(&val.ty.to_string()) {
+        Some(template) => template,
+        None => {
+            return Ok(Declaration {
+                body,
+                ..val.clone()
+            })
+        },
+    };
+    if stack.contains(&val.ty) {
+        let chain = stack
+            .iter()
+            .map(|t| t.to_string())
+            .chain(Some(val.ty.to_string()))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        return Err(syn::Error::new(
+            val.ty.span(),
+            format!("Cyclic templates: {}", chain),
+        ));
+    }
+    let (_, template_base) = template.base.as_ref().expect("Expected a template to have a base");
+    stack.push(val.ty.clone());
+    let base = apply_templates(template_base, templates, stack, overrides);
+    stack.pop();
+    let base = base?;
+
+    let params = match (&val.params, &base.params) {
+        (Some(over), Some(base_params)) => {
+            let (merged, o) = over.merge_over(base_params, &val.ty)?;
+            overrides.extend(o);
+            Some(merged)
+        },
+        (Some(over), None) => Some(over.clone()),
+        (None, base_params) => base_params.clone(),
+    };
+    let trails = match (&val.trails, &base.trails) {
+        (Some(over), Some(base_trails)) => {
+            let mut trails = base_trails.clone();
+            trails.extend(over.iter().cloned());
+            Some(trails)
+        },
+        (over, base_trails) => over.clone().or_else(|| base_trails.clone()),
+    };
+    if body.is_some() && base.body.is_some() {
+        return Err(syn::Error::new(
+            val.ty.span(),
+            format!(
+                "Both the template `{}` and its use define a body, only one of them may",
+                val.ty
+            ),
+        ));
+    }
+    let body = body.or_else(|| base.body.clone());
+    Ok(Declaration {
+        name: val.name.clone(),
+        ty: base.ty.clone(),
+        params,
+        trails,
+        body,
+        base: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::ToTokens;
+
+    use super::ResolvedTemplates;
+    use crate::declaration::Declarations;
+    use crate::define::Defines;
+    use crate::param::Args;
+    use crate::TokenStream2;
+
+    fn resolve<P>(input: &str) -> syn::Result<ResolvedTemplates<TokenStream2, P>>
+    where
+        P: syn::parse::Parse + ToTokens + std::fmt::Display + Clone + super::TemplateParams,
+    {
+        syn::parse_str::<Declarations<TokenStream2, P>>(input)?.resolve_templates()
+    }
+
+    fn tokens(input: &str) -> String {
+        input.parse::<TokenStream2>().unwrap().to_string()
+    }
+
+    #[test]
+    fn args() {
+        let resolved = resolve::<Args>(
+            r#"PrimaryButton = Button(color: Blue, padding: 8); PrimaryButton(label: "Save")"#,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved.declarations.to_token_stream().to_string(),
+            tokens(r#"Button(color: Blue, padding: 8, label: "Save")"#)
+        );
+        assert!(resolved.overrides.is_empty());
+
+        let resolved = resolve::<Args>(
+            "PrimaryButton = Button(color: Blue, padding: 8); PrimaryButton(color: Red)",
+        )
+        .unwrap();
+        assert_eq!(
+            resolved.declarations.to_token_stream().to_string(),
+            tokens("Button(color: Red, padding: 8)")
+        );
+        assert_eq!(resolved.overrides.len(), 1);
+        assert_eq!(resolved.overrides[0].key, "color");
+        assert_eq!(resolved.overrides[0].template, "PrimaryButton");
+    }
+
+    #[test]
+    fn defines() {
+        let resolved = resolve::<Defines>(
+            r#"PrimaryButton = Button(color: Blue, padding: 8); PrimaryButton(label: "Save", color: Red)"#,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved.declarations.to_token_stream().to_string(),
+            tokens(r#"Button(color: Red, padding: 8, label: "Save")"#)
+        );
+
+        let resolved = resolve::<Defines>(
+            "Base = Button(a: 1); Nested = Base(b: 2,); Nested(c: 3, d: 4,)",
+        )
+        .unwrap();
+        assert_eq!(
+            resolved.declarations.to_token_stream().to_string(),
+            tokens("Button(a: 1, b: 2, c: 3, d: 4,)")
+        );
+    }
+
+    #[test]
+    fn params_are_types() {
+        let err = syn::parse_str::<Declarations>("PrimaryButton = Button(color: Blue, padding: 8)")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("see `Args`"), "{}", err);
+    }
+
+    #[test]
+    fn conflicts() {
+        let err = resolve::<Args>("A = Button(a: 1); A = Button(a: 2); A()").err().unwrap();
+        assert_eq!(err.into_iter().count(), 2);
+
+        let err = resolve::<Args>("A = B(); B = A(); A()").err().unwrap();
+        assert!(err.to_string().contains("Cyclic templates"), "{}", err);
+    }
+}